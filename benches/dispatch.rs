@@ -0,0 +1,121 @@
+//! Measures the allocations and the time per dispatch of a wide handler tree
+//! and of a long handler chain, comparing the compiled handlers with the same
+//! handlers combined in the continuation-passing style, as they were executed
+//! before `Handler::compile`.
+//!
+//! Run with `cargo bench --bench dispatch`.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use dptree::{di::DependencyMap, prelude::*};
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+const BRANCHES: u32 = 200;
+const LENGTH: u32 = 1_000;
+const DISPATCHES: u32 = 1_000;
+
+type Bench = Handler<'static, DependencyMap, u32>;
+
+/// How the handlers are combined.
+struct Combinators {
+    chain: fn(Bench, Bench) -> Bench,
+    branch: fn(Bench, Bench) -> Bench,
+}
+
+const COMPILED: Combinators = Combinators { chain: Handler::chain, branch: Handler::branch };
+
+const NESTED: Combinators = Combinators { chain: nested_chain, branch: nested_branch };
+
+/// `first.chain(second)`, with `second` executed as the continuation of
+/// `first`.
+fn nested_chain(first: Bench, second: Bench) -> Bench {
+    dptree::from_fn(move |event, cont| {
+        let second = second.clone();
+        first.clone().execute(event, move |event| second.execute(event, cont))
+    })
+}
+
+/// `first.branch(second)`, with `second` dispatched by the continuation of
+/// `first`.
+fn nested_branch(first: Bench, second: Bench) -> Bench {
+    dptree::from_fn(move |event, cont| {
+        let second = second.clone();
+        first.clone().execute(event, move |event| async move {
+            match second.dispatch(event).await {
+                ControlFlow::Continue(event) => cont(event).await,
+                done => done,
+            }
+        })
+    })
+}
+
+fn tree(c: &Combinators) -> Bench {
+    (0..BRANCHES).fold(dptree::entry(), |handler, i| {
+        let filter = dptree::filter(move |x: u32| x % BRANCHES == i);
+        let map = dptree::map(|x: u32| x as u64);
+        let endpoint = dptree::endpoint(move |y: u64| async move { y as u32 });
+
+        (c.branch)(handler, (c.chain)((c.chain)(filter, map), endpoint))
+    })
+}
+
+fn chain(c: &Combinators) -> Bench {
+    let chain = (0..LENGTH)
+        .fold(dptree::entry(), |handler, _| (c.chain)(handler, dptree::map(|x: u32| x + 1)));
+
+    (c.chain)(chain, dptree::endpoint(|x: u32| async move { x }))
+}
+
+/// Returns the allocations and the time per dispatch.
+fn measure(handler: &Bench, input: u32, output: u32) -> (usize, Duration) {
+    let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    let input = dptree::deps![input];
+
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    for _ in 0..DISPATCHES {
+        let result = runtime.block_on(handler.dispatch(input.clone()));
+        assert_eq!(result, ControlFlow::Break(output));
+    }
+    let elapsed = start.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+
+    (allocations / DISPATCHES as usize, elapsed / DISPATCHES)
+}
+
+fn compare(name: &str, build: fn(&Combinators) -> Bench, input: u32, output: u32) {
+    let (compiled, compiled_time) = measure(&build(&COMPILED).compile(), input, output);
+    let (nested, nested_time) = measure(&build(&NESTED), input, output);
+
+    println!(
+        "{}: {} allocations, {:?} per dispatch (nested: {} allocations, {:?})",
+        name, compiled, compiled_time, nested, nested_time
+    );
+    assert!(compiled < nested, "The compiled {} must allocate less than the nested one", name);
+}
+
+fn main() {
+    compare("tree", tree, BRANCHES - 1, BRANCHES - 1);
+    compare("chain", chain, 0, LENGTH);
+}
@@ -34,13 +34,15 @@ async fn repl(mut state: CommandState, dispatcher: Handler<'static, Store, Comma
         let event = Event::parse(str);
 
         let new_state = match event {
-            Some(event) => match dispatcher.dispatch(dptree::deps![event, state.clone()]).await {
-                ControlFlow::Break(new_state) => new_state,
-                ControlFlow::Continue(_) => {
-                    println!("There is no transition for the event");
-                    continue;
+            Some(event) => {
+                match dispatcher.dispatch_traced(dptree::deps![event, state.clone()]).await {
+                    (ControlFlow::Break(new_state), _) => new_state,
+                    (ControlFlow::Continue(_), trace) => {
+                        println!("There is no transition for the event. Trace:\n{}", trace);
+                        continue;
+                    }
                 }
-            },
+            }
             _ => {
                 println!("Unknown event");
                 continue;
@@ -1,4 +1,4 @@
-use dptree::prelude::*;
+use dptree::{prelude::*, DispatchError};
 use std::{net::Ipv4Addr, sync::Arc};
 
 type Store = Arc<DependencyMap>;
@@ -21,15 +21,17 @@ async fn main() {
 
     let str_num_handler = assert_num_string_handler(10u32, "Hello");
 
-    str_num_handler.dispatch(store.clone()).await;
+    let _ = str_num_handler.dispatch(store.clone()).await;
 
-    // This will cause a panic because we do not store `Ipv4Addr` in out store.
-    let handle = tokio::spawn(async move {
-        let ip_handler: Endpoint<_, _> = dptree::endpoint(|ip: Ipv4Addr| async move {
-            assert_eq!(ip, Ipv4Addr::new(0, 0, 0, 0));
-        });
-        ip_handler.dispatch(store.clone()).await;
+    // `dispatch` would panic here because we do not store `Ipv4Addr` in our store,
+    // but `try_dispatch` returns an error instead.
+    let ip_handler: Endpoint<_, _> = dptree::endpoint(|ip: Ipv4Addr| async move {
+        assert_eq!(ip, Ipv4Addr::new(0, 0, 0, 0));
     });
-    let result = handle.await;
-    assert!(result.is_err())
+    let result = ip_handler.try_dispatch(store.clone()).await;
+    assert!(matches!(result, Err(DispatchError::MissingDependency(_))));
+
+    if let Err(error) = result {
+        println!("{}", error);
+    }
 }
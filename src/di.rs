@@ -15,8 +15,9 @@ use futures::future::{ready, BoxFuture};
 use std::{
     any::{Any, TypeId},
     collections::HashMap,
-    fmt::{Debug, Formatter, Write},
+    fmt::{Debug, Display, Formatter},
     future::Future,
+    hash::{Hash, Hasher},
     ops::Deref,
     sync::Arc,
 };
@@ -27,9 +28,9 @@ use std::{
 /// cannot return a value of specified type:
 ///
 /// 1. Do not implement [`DependencySupplier`] for the type. It often requires
-/// some type-level manipulations.
+///    some type-level manipulations.
 /// 2. Runtime panic. Be careful in this case: check whether you add your type
-/// to the container.
+///    to the container.
 ///
 /// A concrete solution is left to a particular implementation.
 pub trait DependencySupplier<Value> {
@@ -37,8 +38,120 @@ pub trait DependencySupplier<Value> {
     ///
     /// We assume that all values are stored in `Arc<_>`.
     fn get(&self) -> Arc<Value>;
+
+    /// Get the value or return [`MissingDependency`] if it is absent.
+    ///
+    /// ## Default implementation
+    ///
+    /// By default this returns the value from
+    /// [`get`](DependencySupplier::get), which means that containers that
+    /// do not override this method keep their panicking behaviour.
+    fn try_get(&self) -> Result<Arc<Value>, MissingDependency> {
+        Ok(self.get())
+    }
+
+    /// Information about `Value`, as it is stored in the container.
+    ///
+    /// This is used for static analysis of handlers (see
+    /// [`DependencyFlow`](crate::description::DependencyFlow)).
+    ///
+    /// ## Default implementation
+    ///
+    /// By default this returns `None`, meaning that the container cannot
+    /// describe its types.
+    fn supplied_type() -> Option<TypeInfo>
+    where
+        Self: Sized,
+    {
+        None
+    }
+}
+
+/// Run-time information about a type.
+#[derive(Debug, Clone, Copy)]
+pub struct TypeInfo {
+    id: TypeId,
+    name: &'static str,
+}
+
+impl TypeInfo {
+    /// Returns information about `T`.
+    pub fn of<T: ?Sized + 'static>() -> Self {
+        Self { id: TypeId::of::<T>(), name: std::any::type_name::<T>() }
+    }
+
+    /// The identifier of the type.
+    pub fn id(&self) -> TypeId {
+        self.id
+    }
+
+    /// The name of the type, as returned by [`std::any::type_name`].
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+impl PartialEq for TypeInfo {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
 }
 
+impl Eq for TypeInfo {}
+
+impl Hash for TypeInfo {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+impl Display for TypeInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name)
+    }
+}
+
+/// An error that occurs when a requested type is missing from a container.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingDependency {
+    type_name: &'static str,
+    available_types: Vec<&'static str>,
+}
+
+impl MissingDependency {
+    /// Constructs an error for the requested type `type_name`.
+    ///
+    /// `available_types` are the names of the types that the container does
+    /// provide.
+    pub fn new(type_name: &'static str, available_types: Vec<&'static str>) -> Self {
+        Self { type_name, available_types }
+    }
+
+    /// The name of the requested type.
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+
+    /// The names of the types that were available in the container.
+    pub fn available_types(&self) -> &[&'static str] {
+        &self.available_types
+    }
+}
+
+impl Display for MissingDependency {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{} was requested, but not provided. Available types:", self.type_name)?;
+
+        for type_name in &self.available_types {
+            writeln!(f, "    {}", type_name)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for MissingDependency {}
+
 /// A DI container with multiple dependencies.
 ///
 /// This DI container stores types by their corresponding type identifiers. It
@@ -66,7 +179,20 @@ pub trait DependencySupplier<Value> {
 /// assert_eq!(container.get(), Arc::new(10_i32));
 /// ```
 ///
-/// When a value is not found within the container, it will panic:
+/// When a value is not found within the container, [`DependencySupplier::try_get`]
+/// returns an error:
+///
+/// ```
+/// # use std::sync::Arc;
+/// use dptree::di::{DependencyMap, DependencySupplier, MissingDependency};
+///
+/// let container = dptree::deps![10i32];
+///
+/// let string: Result<Arc<String>, MissingDependency> = container.try_get();
+/// assert_eq!(string.unwrap_err().type_name(), std::any::type_name::<String>());
+/// ```
+///
+/// ...and [`DependencySupplier::get`] panics:
 ///
 /// ```should_panic
 /// # use std::sync::Arc;
@@ -99,7 +225,7 @@ impl PartialEq for DependencyMap {
     fn eq(&self, other: &Self) -> bool {
         let keys1 = self.map.keys();
         let keys2 = other.map.keys();
-        keys1.zip(keys2).map(|(k1, k2)| k1 == k2).all(|x| x)
+        keys1.zip(keys2).all(|(k1, k2)| k1 == k2)
     }
 }
 
@@ -136,14 +262,13 @@ impl DependencyMap {
             .map(|dep| dep.inner.downcast().expect("Values are stored by TypeId"))
     }
 
-    fn available_types(&self) -> String {
-        let mut list = String::new();
-
-        for dep in self.map.values() {
-            writeln!(list, "    {}", dep.type_name).unwrap();
-        }
+    /// Returns information about all types stored in the container.
+    pub fn types(&self) -> Vec<TypeInfo> {
+        self.map.iter().map(|(&id, dep)| TypeInfo { id, name: dep.type_name }).collect()
+    }
 
-        list
+    fn available_types(&self) -> Vec<&'static str> {
+        self.map.values().map(|dep| dep.type_name).collect()
     }
 }
 
@@ -158,19 +283,19 @@ where
     V: Send + Sync + 'static,
 {
     fn get(&self) -> Arc<V> {
-        self.map
-            .get(&TypeId::of::<V>())
-            .unwrap_or_else(|| {
-                panic!(
-                    "{} was requested, but not provided. Available types:\n{}",
-                    std::any::type_name::<V>(),
-                    self.available_types()
-                )
-            })
-            .clone()
-            .inner
-            .downcast::<V>()
-            .expect("Checked by .unwrap_or_else()")
+        self.try_get().unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    fn try_get(&self) -> Result<Arc<V>, MissingDependency> {
+        let dep = self.map.get(&TypeId::of::<V>()).ok_or_else(|| {
+            MissingDependency::new(std::any::type_name::<V>(), self.available_types())
+        })?;
+
+        Ok(dep.inner.clone().downcast::<V>().expect("Values are stored by TypeId"))
+    }
+
+    fn supplied_type() -> Option<TypeInfo> {
+        Some(TypeInfo::of::<V>())
     }
 }
 
@@ -181,6 +306,14 @@ where
     fn get(&self) -> Arc<V> {
         self.deref().get()
     }
+
+    fn try_get(&self) -> Result<Arc<V>, MissingDependency> {
+        self.deref().try_get()
+    }
+
+    fn supplied_type() -> Option<TypeInfo> {
+        S::supplied_type()
+    }
 }
 
 /// Converts functions into [`CompiledFn`].
@@ -188,11 +321,46 @@ where
 /// The function must follow some rules, to be usable with DI:
 ///
 /// 1. For each function parameter of type `T`, `Input` must satisfy
-/// `DependencySupplier<T>`.
+///    `DependencySupplier<T>`.
 /// 2. The function must be of 0-12 arguments.
 /// 3. The function must return [`Future`].
 pub trait Injectable<Input, Output, FnArgs> {
     fn inject<'a>(&'a self, container: &'a Input) -> CompiledFn<'a, Output>;
+
+    /// The fallible version of [`Injectable::inject`].
+    ///
+    /// Instead of panicking when a dependency is missing, this method returns
+    /// [`MissingDependency`] for the first dependency that cannot be found in
+    /// `container`.
+    ///
+    /// ## Default implementation
+    ///
+    /// By default this returns the value from
+    /// [`inject`](Injectable::inject).
+    fn try_inject<'a>(
+        &'a self,
+        container: &'a Input,
+    ) -> Result<CompiledFn<'a, Output>, MissingDependency>
+    where
+        FnArgs: 'a,
+    {
+        Ok(self.inject(container))
+    }
+
+    /// Types that this function requests from a container.
+    ///
+    /// Types for which `Input` returns `None` from
+    /// [`DependencySupplier::supplied_type`] are omitted.
+    ///
+    /// ## Default implementation
+    ///
+    /// By default this returns an empty list.
+    fn input_types() -> Vec<TypeInfo>
+    where
+        Self: Sized,
+    {
+        Vec::new()
+    }
 }
 
 /// A function with all dependencies satisfied.
@@ -220,6 +388,26 @@ macro_rules! impl_into_di {
                     Box::pin(fut)
                 })
             }
+
+            #[allow(non_snake_case)]
+            #[allow(unused_variables)]
+            fn try_inject<'a>(&'a self, container: &'a Input) -> Result<CompiledFn<'a, Output>, MissingDependency>
+            where
+                ($($generic,)*): 'a,
+            {
+                // Take all dependencies upfront, so that the compiled function cannot panic.
+                $(let $generic: Arc<$generic> = DependencySupplier::<$generic>::try_get(container)?;)*
+                Ok(Arc::new(move || {
+                    $(let $generic = std::borrow::Borrow::<$generic>::borrow(&$generic).clone();)*
+                    let fut = self( $( $generic ),* );
+                    Box::pin(fut)
+                }))
+            }
+
+            fn input_types() -> Vec<TypeInfo> {
+                let types: Vec<Option<TypeInfo>> = vec![$(<Input as DependencySupplier<$generic>>::supplied_type()),*];
+                types.into_iter().flatten().collect()
+            }
         }
 
         impl<Func, Input, Output, $($generic),*> Injectable<Input, Output, ($($generic,)*)> for Asyncify<Func>
@@ -240,6 +428,27 @@ macro_rules! impl_into_di {
                     Box::pin(ready(out))
                 })
             }
+
+            #[allow(non_snake_case)]
+            #[allow(unused_variables)]
+            fn try_inject<'a>(&'a self, container: &'a Input) -> Result<CompiledFn<'a, Output>, MissingDependency>
+            where
+                ($($generic,)*): 'a,
+            {
+                // Take all dependencies upfront, so that the compiled function cannot panic.
+                $(let $generic: Arc<$generic> = DependencySupplier::<$generic>::try_get(container)?;)*
+                let Asyncify(this) = self;
+                Ok(Arc::new(move || {
+                    $(let $generic = std::borrow::Borrow::<$generic>::borrow(&$generic).clone();)*
+                    let out = this( $( $generic ),* );
+                    Box::pin(ready(out))
+                }))
+            }
+
+            fn input_types() -> Vec<TypeInfo> {
+                let types: Vec<Option<TypeInfo>> = vec![$(<Input as DependencySupplier<$generic>>::supplied_type()),*];
+                types.into_iter().flatten().collect()
+            }
         }
     };
 }
@@ -292,12 +501,29 @@ macro_rules! deps {
 pub trait Insert<Value> {
     /// Inserts `value` into itself, returning the previous value, if exists.
     fn insert(&mut self, value: Value) -> Option<Arc<Value>>;
+
+    /// Information about `Value`, as it is stored in the container.
+    ///
+    /// ## Default implementation
+    ///
+    /// By default this returns `None`, meaning that the container cannot
+    /// describe its types.
+    fn inserted_type() -> Option<TypeInfo>
+    where
+        Self: Sized,
+    {
+        None
+    }
 }
 
 impl<T: Send + Sync + 'static> Insert<T> for DependencyMap {
     fn insert(&mut self, value: T) -> Option<Arc<T>> {
         DependencyMap::insert(self, value)
     }
+
+    fn inserted_type() -> Option<TypeInfo> {
+        Some(TypeInfo::of::<T>())
+    }
 }
 
 #[cfg(test)]
@@ -315,4 +541,45 @@ mod tests {
         assert_eq!(map.get(), Arc::new("hello world"));
         assert_eq!(map.get(), Arc::new(true));
     }
+
+    #[test]
+    fn try_get() {
+        let map = deps![42i32, "hello world"];
+
+        assert_eq!(map.try_get(), Ok(Arc::new(42i32)));
+
+        let error = DependencySupplier::<bool>::try_get(&map).unwrap_err();
+        assert_eq!(error.type_name(), "bool");
+
+        let mut available = error.available_types().to_vec();
+        available.sort_unstable();
+        assert_eq!(available, ["&str", "i32"]);
+    }
+
+    #[tokio::test]
+    async fn try_inject() {
+        let map = deps![42i32];
+
+        let f = |x: i32, _: bool| async move { x };
+        let error = Injectable::<_, i32, _>::try_inject(&f, &map).err().unwrap();
+        assert_eq!(error.type_name(), "bool");
+
+        let f = Asyncify(|x: i32| x + 1);
+        let compiled = Injectable::<_, i32, _>::try_inject(&f, &map).unwrap();
+        assert_eq!((compiled().await, compiled().await), (43, 43));
+    }
+
+    #[test]
+    fn input_types() {
+        fn input_types<F, Output, Args>(_: &F) -> Vec<TypeInfo>
+        where
+            F: Injectable<DependencyMap, Output, Args>,
+        {
+            F::input_types()
+        }
+
+        let f = |_: i32, _: &'static str| async {};
+        assert_eq!(input_types(&f), [TypeInfo::of::<i32>(), TypeInfo::of::<&str>()]);
+        assert_eq!(input_types(&Asyncify(|| true)), []);
+    }
 }
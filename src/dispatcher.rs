@@ -0,0 +1,627 @@
+//! Dispatching a stream of events.
+//!
+//! See [`Dispatcher`].
+
+use std::{
+    any::Any,
+    collections::{HashMap, VecDeque},
+    future::Future,
+    hash::{Hash, Hasher},
+    ops::ControlFlow,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use futures::{
+    future::{poll_fn, BoxFuture},
+    stream::FuturesUnordered,
+    FutureExt, Stream, StreamExt,
+};
+
+use crate::{
+    description,
+    di::{Asyncify, Injectable},
+    Handler, HandlerDescription,
+};
+
+/// Runs the tasks of [`Dispatcher`].
+///
+/// This lets the dispatcher run on top of any asynchronous runtime. It is
+/// implemented for functions, so that, for example, a tokio spawner can be
+/// written as
+/// `|task| Box::pin(async { tokio::spawn(task).await.unwrap() }) as BoxFuture<_>`.
+pub trait Spawner: Send + Sync {
+    /// Spawns `task`, returning a future that resolves when `task` completes.
+    fn spawn(&self, task: BoxFuture<'static, ()>) -> BoxFuture<'static, ()>;
+}
+
+impl<F> Spawner for F
+where
+    F: Fn(BoxFuture<'static, ()>) -> BoxFuture<'static, ()> + Send + Sync,
+{
+    fn spawn(&self, task: BoxFuture<'static, ()>) -> BoxFuture<'static, ()> {
+        self(task)
+    }
+}
+
+type Unhandled<Input> = dyn Fn(Input) -> BoxFuture<'static, ()> + Send + Sync;
+
+/// Computes the key of an event, if its dependencies are provided.
+type KeyFn<Input> = dyn Fn(Input) -> BoxFuture<'static, (Input, Option<Key>)> + Send + Sync;
+
+/// A distribution key of any type.
+#[derive(Clone)]
+struct Key(Arc<dyn DynKey>);
+
+trait DynKey: Send + Sync {
+    fn as_any(&self) -> &dyn Any;
+
+    fn dyn_eq(&self, other: &dyn Any) -> bool;
+
+    fn dyn_hash(&self, state: &mut dyn Hasher);
+}
+
+impl<K> DynKey for K
+where
+    K: Hash + Eq + Send + Sync + 'static,
+{
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn dyn_eq(&self, other: &dyn Any) -> bool {
+        other.downcast_ref::<K>() == Some(self)
+    }
+
+    fn dyn_hash(&self, mut state: &mut dyn Hasher) {
+        self.hash(&mut state)
+    }
+}
+
+impl PartialEq for Key {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.dyn_eq(other.0.as_any())
+    }
+}
+
+impl Eq for Key {}
+
+impl Hash for Key {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.dyn_hash(state)
+    }
+}
+
+/// The events waiting for the events with the same key.
+struct Queues<Input> {
+    /// The keys of the events being dispatched or waiting, each with the
+    /// waiting events. An idle key is removed.
+    waiting: HashMap<Key, VecDeque<Input>>,
+    /// The keys whose next event can be dispatched, in order of arrival.
+    ready: VecDeque<Key>,
+    /// The number of the waiting events.
+    len: usize,
+}
+
+impl<Input> Queues<Input> {
+    fn new() -> Self {
+        Self { waiting: HashMap::new(), ready: VecDeque::new(), len: 0 }
+    }
+
+    fn push(&mut self, key: Key, event: Input) {
+        self.len += 1;
+
+        match self.waiting.get_mut(&key) {
+            Some(events) => events.push_back(event),
+            None => {
+                self.waiting.insert(key.clone(), VecDeque::from(vec![event]));
+                self.ready.push_back(key);
+            }
+        }
+    }
+
+    fn pop_ready(&mut self) -> Option<(Key, Input)> {
+        let key = self.ready.pop_front()?;
+        let event = self.waiting.get_mut(&key).and_then(VecDeque::pop_front)?;
+        self.len -= 1;
+        Some((key, event))
+    }
+
+    fn finish(&mut self, key: Key) {
+        match self.waiting.get(&key) {
+            Some(events) if events.is_empty() => {
+                self.waiting.remove(&key);
+            }
+            _ => self.ready.push_back(key),
+        }
+    }
+}
+
+/// Dispatches a stream of events with a handler.
+///
+/// Each event is dispatched with [`Handler::dispatch`]; the outputs are
+/// dropped, and the events that are not handled, i.e., for which the handler
+/// continues the execution, are passed to [`Dispatcher::unhandled`]. Up to
+/// [`Dispatcher::concurrency_limit`] events are dispatched at a time; the
+/// dispatcher stops taking events from the stream when this limit is reached.
+///
+/// By default, events are dispatched concurrently within the future returned
+/// by [`Dispatcher::run`], so that they are not executed in parallel; to spawn
+/// them as tasks of your runtime instead, set [`Dispatcher::spawner`]. To
+/// dispatch the events with the same key (e.g., a chat ID) one by one, set
+/// [`Dispatcher::distribution_key`].
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::{
+///     atomic::{AtomicI32, Ordering},
+///     Arc,
+/// };
+///
+/// use dptree::{prelude::*, Dispatcher};
+/// use futures::StreamExt;
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let sum = Arc::new(AtomicI32::new(0));
+/// let unhandled = Arc::new(AtomicI32::new(0));
+///
+/// let handler: Handler<_, _> = dptree::filter(|x: i32| x > 0).endpoint({
+///     let sum = Arc::clone(&sum);
+///     move |x: i32| {
+///         let sum = Arc::clone(&sum);
+///         async move {
+///             sum.fetch_add(x, Ordering::Relaxed);
+///         }
+///     }
+/// });
+///
+/// Dispatcher::new(handler)
+///     .concurrency_limit(2)
+///     .unhandled({
+///         let unhandled = Arc::clone(&unhandled);
+///         move |_: DependencyMap| {
+///             let unhandled = Arc::clone(&unhandled);
+///             async move {
+///                 unhandled.fetch_add(1, Ordering::Relaxed);
+///             }
+///         }
+///     })
+///     .run(futures::stream::iter(vec![1, -2, 3]).map(|x| dptree::deps![x]))
+///     .await;
+///
+/// assert_eq!(sum.load(Ordering::Relaxed), 4);
+/// assert_eq!(unhandled.load(Ordering::Relaxed), 1);
+/// # }
+/// ```
+#[must_use]
+pub struct Dispatcher<Input, Output, Descr = description::Unspecified> {
+    handler: Handler<'static, Input, Output, Descr>,
+    concurrency_limit: usize,
+    queue_limit: usize,
+    unhandled: Arc<Unhandled<Input>>,
+    spawner: Option<Arc<dyn Spawner>>,
+    key: Option<Arc<KeyFn<Input>>>,
+}
+
+impl<Input, Output, Descr> Clone for Dispatcher<Input, Output, Descr> {
+    fn clone(&self) -> Self {
+        Self {
+            handler: self.handler.clone(),
+            concurrency_limit: self.concurrency_limit,
+            queue_limit: self.queue_limit,
+            unhandled: Arc::clone(&self.unhandled),
+            spawner: self.spawner.clone(),
+            key: self.key.clone(),
+        }
+    }
+}
+
+impl<Input, Output, Descr> Dispatcher<Input, Output, Descr>
+where
+    Input: Send + 'static,
+    Output: Send + 'static,
+    Descr: HandlerDescription,
+{
+    /// The default of [`Dispatcher::queue_limit`].
+    pub const DEFAULT_QUEUE_LIMIT: usize = 1024;
+
+    /// Constructs a dispatcher with no concurrency limit, which ignores the
+    /// unhandled events.
+    pub fn new(handler: Handler<'static, Input, Output, Descr>) -> Self {
+        Self {
+            handler,
+            concurrency_limit: usize::MAX,
+            queue_limit: Self::DEFAULT_QUEUE_LIMIT,
+            unhandled: Arc::new(|_| Box::pin(async {})),
+            spawner: None,
+            key: None,
+        }
+    }
+
+    /// Sets the maximum number of events dispatched at a time.
+    ///
+    /// With [`Dispatcher::distribution_key`], this is the maximum number of
+    /// keys whose events are dispatched at a time.
+    ///
+    /// # Panics
+    ///
+    /// If `limit` is zero.
+    pub fn concurrency_limit(mut self, limit: usize) -> Self {
+        assert!(limit > 0, "The concurrency limit must be positive");
+        self.concurrency_limit = limit;
+        self
+    }
+
+    /// Sets the maximum number of events waiting in the queues of the keys
+    /// (see [`Dispatcher::distribution_key`]).
+    ///
+    /// Once this limit is reached, the dispatcher stops taking events from the
+    /// stream until some of the waiting events are dispatched. By default,
+    /// this is [`Dispatcher::DEFAULT_QUEUE_LIMIT`].
+    ///
+    /// # Panics
+    ///
+    /// If `limit` is zero.
+    pub fn queue_limit(mut self, limit: usize) -> Self {
+        assert!(limit > 0, "The queue limit must be positive");
+        self.queue_limit = limit;
+        self
+    }
+
+    /// Sets the function executed with the events that are not handled.
+    pub fn unhandled<F, Fut>(mut self, f: F) -> Self
+    where
+        F: Fn(Input) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.unhandled = Arc::new(move |event| Box::pin(f(event)));
+        self
+    }
+
+    /// Sets the spawner of the dispatched events.
+    pub fn spawner<S>(mut self, spawner: S) -> Self
+    where
+        S: Spawner + 'static,
+    {
+        self.spawner = Some(Arc::new(spawner));
+        self
+    }
+
+    /// Dispatches the events with the same key, computed by `key` from the
+    /// dependencies of an event, one by one, in order of arrival.
+    ///
+    /// The events with different keys are still dispatched concurrently, up to
+    /// [`Dispatcher::concurrency_limit`] at a time. An event with a busy key
+    /// waits in the queue of the key, so the dispatcher takes events from the
+    /// stream until [`Dispatcher::queue_limit`] events are waiting; the queue
+    /// is removed as soon as it is empty and there is no event with this key
+    /// being dispatched. The events whose dependencies of `key` are missing are
+    /// passed to [`Dispatcher::unhandled`] right away, without taking the
+    /// places of the dispatched events; up to [`Dispatcher::concurrency_limit`]
+    /// of them are passed at a time.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// use dptree::{prelude::*, Dispatcher};
+    /// use futures::StreamExt;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let log = Arc::new(Mutex::new(Vec::new()));
+    ///
+    /// let handler: Handler<_, _> = dptree::endpoint({
+    ///     let log = Arc::clone(&log);
+    ///     move |(chat, text): (u32, &'static str)| {
+    ///         let log = Arc::clone(&log);
+    ///         async move { log.lock().unwrap().push((chat, text)) }
+    ///     }
+    /// });
+    ///
+    /// let messages: Vec<(u32, &str)> = vec![(1, "a"), (2, "b"), (1, "c")];
+    /// Dispatcher::new(handler)
+    ///     .distribution_key(|(chat, _): (u32, &'static str)| chat)
+    ///     .run(futures::stream::iter(messages).map(|message| dptree::deps![message]))
+    ///     .await;
+    ///
+    /// let log = log.lock().unwrap();
+    /// let chat_1: Vec<_> = log.iter().filter(|(chat, _)| *chat == 1).collect();
+    /// assert_eq!(chat_1, [&(1, "a"), &(1, "c")]);
+    /// # }
+    /// ```
+    #[track_caller]
+    pub fn distribution_key<F, K, Args>(mut self, key: F) -> Self
+    where
+        Asyncify<F>: Injectable<Input, K, Args> + Send + Sync + 'static,
+        K: Hash + Eq + Send + Sync + 'static,
+    {
+        let key = Arc::new(Asyncify(key));
+
+        self.key = Some(Arc::new(move |event| {
+            let key = Arc::clone(&key);
+
+            Box::pin(async move {
+                let key = match key.try_inject(&event) {
+                    Ok(key) => Some(Key(Arc::new(key().await))),
+                    Err(_) => None,
+                };
+                (event, key)
+            })
+        }));
+        self
+    }
+
+    /// Dispatches the events of `stream` until it ends, then waits for the
+    /// events being dispatched.
+    pub async fn run<S>(&self, stream: S)
+    where
+        S: Stream<Item = Input>,
+    {
+        self.run_until(stream, futures::future::pending()).await
+    }
+
+    /// Dispatches the events of `stream` until it ends or `shutdown`
+    /// completes, then waits for the events being dispatched.
+    ///
+    /// The events that are not taken from `stream` by then are left there.
+    pub async fn run_until<S, F>(&self, stream: S, shutdown: F)
+    where
+        S: Stream<Item = Input>,
+        F: Future<Output = ()>,
+    {
+        let stream = stream.take_until(shutdown);
+        futures::pin_mut!(stream);
+
+        let mut in_flight = FuturesUnordered::new();
+        let mut rejected = FuturesUnordered::new();
+        let mut queues = Queues::new();
+        let mut keying: Option<BoxFuture<(Input, Option<Key>)>> = None;
+        let mut exhausted = false;
+
+        poll_fn(|cx: &mut Context<'_>| loop {
+            while let Poll::Ready(Some(key)) = in_flight.poll_next_unpin(cx) {
+                if let Some(key) = key {
+                    queues.finish(key);
+                }
+            }
+            while let Poll::Ready(Some(())) = rejected.poll_next_unpin(cx) {}
+
+            if in_flight.len() < self.concurrency_limit {
+                if let Some((key, event)) = queues.pop_ready() {
+                    in_flight.push(self.spawn(Some(key), event));
+                    continue;
+                }
+            }
+
+            if let Some(fut) = &mut keying {
+                match fut.poll_unpin(cx) {
+                    Poll::Ready((event, key)) => {
+                        keying = None;
+                        match key {
+                            Some(key) => queues.push(key, event),
+                            None => rejected.push((self.unhandled)(event)),
+                        }
+                        continue;
+                    }
+                    Poll::Pending => {}
+                }
+            } else if !exhausted
+                && match self.key {
+                    Some(_) => {
+                        queues.len < self.queue_limit && rejected.len() < self.concurrency_limit
+                    }
+                    None => in_flight.len() < self.concurrency_limit,
+                }
+            {
+                match stream.poll_next_unpin(cx) {
+                    Poll::Ready(Some(event)) => {
+                        match &self.key {
+                            Some(key) => keying = Some(key(event)),
+                            None => in_flight.push(self.spawn(None, event)),
+                        }
+                        continue;
+                    }
+                    Poll::Ready(None) => exhausted = true,
+                    Poll::Pending => {}
+                }
+            }
+
+            return match exhausted
+                && keying.is_none()
+                && in_flight.is_empty()
+                && rejected.is_empty()
+            {
+                true => Poll::Ready(()),
+                false => Poll::Pending,
+            };
+        })
+        .await
+    }
+
+    /// Dispatches `event`, returning a future that resolves to `key` when the
+    /// dispatch completes.
+    fn spawn(&self, key: Option<Key>, event: Input) -> BoxFuture<'static, Option<Key>> {
+        let handler = self.handler.clone();
+        let unhandled = Arc::clone(&self.unhandled);
+
+        let task = Box::pin(async move {
+            if let ControlFlow::Continue(event) = handler.dispatch(event).await {
+                unhandled(event).await;
+            }
+        });
+
+        let task = match &self.spawner {
+            Some(spawner) => spawner.spawn(task),
+            None => task,
+        };
+        Box::pin(task.map(move |()| key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Mutex,
+        },
+        time::Duration,
+    };
+
+    use futures::stream;
+
+    use super::*;
+    use crate::{deps, di::DependencyMap, endpoint, filter};
+
+    #[derive(Default)]
+    struct Counters {
+        started: AtomicUsize,
+        running: AtomicUsize,
+        max_running: AtomicUsize,
+    }
+
+    /// A dispatcher that spawns the events onto tokio, whose handler sleeps
+    /// for the positive events and passes the others to `unhandled`.
+    fn sleeping(
+        counters: &Arc<Counters>,
+        unhandled: &Arc<Mutex<Vec<DependencyMap>>>,
+    ) -> Dispatcher<DependencyMap, ()> {
+        let handler = filter(|x: i32| x > 0).chain(endpoint({
+            let counters = Arc::clone(counters);
+            move || {
+                counters.started.fetch_add(1, Ordering::SeqCst);
+                let counters = Arc::clone(&counters);
+                async move {
+                    let now = counters.running.fetch_add(1, Ordering::SeqCst) + 1;
+                    counters.max_running.fetch_max(now, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    counters.running.fetch_sub(1, Ordering::SeqCst);
+                }
+            }
+        }));
+
+        Dispatcher::new(handler)
+            .unhandled({
+                let unhandled = Arc::clone(unhandled);
+                move |container: DependencyMap| {
+                    unhandled.lock().unwrap().push(container);
+                    async {}
+                }
+            })
+            .spawner(|task| Box::pin(async { tokio::spawn(task).await.unwrap() }) as BoxFuture<_>)
+    }
+
+    // The time is paused in the tests, so the timers fire in the order of
+    // their deadlines, however loaded the machine is.
+    #[tokio::test(start_paused = true)]
+    async fn concurrency_limit() {
+        let (counters, unhandled) = Default::default();
+        let dispatcher = sleeping(&counters, &unhandled).concurrency_limit(2);
+
+        dispatcher.run(stream::iter(vec![1, 2, -3, 4, 5]).map(|x| deps![x])).await;
+        assert_eq!(counters.started.load(Ordering::SeqCst), 4);
+        assert_eq!(counters.running.load(Ordering::SeqCst), 0);
+        assert_eq!(counters.max_running.load(Ordering::SeqCst), 2);
+        assert_eq!(*unhandled.lock().unwrap(), [deps![-3]]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn run_until() {
+        let (counters, unhandled) = Default::default();
+        let dispatcher = sleeping(&counters, &unhandled).concurrency_limit(2);
+
+        // The events being dispatched on shutdown (two, by the limit) are
+        // drained, and the rest are not taken: the shutdown happens before the
+        // handlers finish.
+        let events = stream::iter(vec![1, 2, 3]).chain(stream::pending()).map(|x| deps![x]);
+        dispatcher.run_until(events, tokio::time::sleep(Duration::from_millis(25))).await;
+        assert_eq!(counters.started.load(Ordering::SeqCst), 2);
+        assert_eq!(counters.running.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn distribution_key() {
+        let (counters, unhandled) = Default::default();
+        let dispatcher =
+            sleeping(&counters, &unhandled).distribution_key(|x: i32| x % 3).concurrency_limit(4);
+
+        // The events with the same key are dispatched one by one.
+        dispatcher.run(stream::iter(vec![1, 4, 7, 2, 5, 8]).map(|x| deps![x])).await;
+        assert_eq!(counters.started.load(Ordering::SeqCst), 6);
+        assert_eq!(counters.max_running.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn distribution_key_with_concurrency_limit() {
+        let (counters, unhandled) = Default::default();
+        let dispatcher =
+            sleeping(&counters, &unhandled).distribution_key(|x: i32| x).concurrency_limit(3);
+
+        dispatcher.run(stream::iter(1..=6).map(|x| deps![x])).await;
+        assert_eq!(counters.max_running.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn missing_key() {
+        let (counters, unhandled) = Default::default();
+        let dispatcher = sleeping(&counters, &unhandled).distribution_key(|x: i32| x);
+
+        let events = stream::iter(vec![deps![1], deps!["no key"], deps![2]]);
+        dispatcher.run(events).await;
+        assert_eq!(counters.started.load(Ordering::SeqCst), 2);
+        assert_eq!(*unhandled.lock().unwrap(), [deps!["no key"]]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn queue_limit() {
+        let (counters, unhandled): (Arc<Counters>, _) = Default::default();
+        let dispatcher =
+            sleeping(&counters, &unhandled).distribution_key(|_: i32| ()).queue_limit(2);
+
+        // The dispatcher stops taking events once the queues are full.
+        let taken = Arc::new(AtomicUsize::new(0));
+        let max_waiting = Arc::new(AtomicUsize::new(0));
+        let events = stream::iter(1..=6).inspect({
+            let counters = Arc::clone(&counters);
+            let taken = Arc::clone(&taken);
+            let max_waiting = Arc::clone(&max_waiting);
+            move |_| {
+                let waiting = taken.fetch_add(1, Ordering::SeqCst) + 1
+                    - counters.started.load(Ordering::SeqCst);
+                max_waiting.fetch_max(waiting, Ordering::SeqCst);
+            }
+        });
+        dispatcher.run(events.map(|x| deps![x])).await;
+        assert_eq!(counters.started.load(Ordering::SeqCst), 6);
+        // Two events in the queue and one spawned, but not started yet.
+        assert_eq!(max_waiting.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn rejected_events() {
+        let start = tokio::time::Instant::now();
+        let started_at = Arc::new(Mutex::new(Vec::new()));
+
+        let handler: Handler<DependencyMap, ()> = endpoint({
+            let started_at = Arc::clone(&started_at);
+            move || {
+                started_at.lock().unwrap().push(start.elapsed());
+                async {}
+            }
+        });
+        let dispatcher = Dispatcher::new(handler)
+            .concurrency_limit(2)
+            .distribution_key(|x: i32| x)
+            .unhandled(|_: DependencyMap| tokio::time::sleep(Duration::from_millis(50)));
+
+        // The slow rejection of the event with no key does not delay the others.
+        let events = stream::iter(vec![deps!["no key"], deps![1], deps![2]]);
+        dispatcher.run(events).await;
+        assert_eq!(*started_at.lock().unwrap(), [Duration::ZERO, Duration::ZERO]);
+        assert_eq!(start.elapsed(), Duration::from_millis(50));
+    }
+}
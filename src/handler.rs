@@ -1,16 +1,48 @@
+mod around;
+mod broadcast;
+mod catch_unwind;
+mod compile;
+mod context;
 mod core;
 pub mod description;
+mod dynamic_branches;
 mod endpoint;
+mod error;
 mod filter;
 mod filter_map;
+mod first_of;
 mod inspect;
+mod kinds;
 mod map;
 mod methods;
+pub mod observer;
+mod on_error;
+mod race;
+pub mod rate_limit;
+pub mod router;
+mod spans;
+pub mod static_handler;
+mod swappable;
+mod switch;
+#[cfg(feature = "tower")]
+mod tower;
+pub mod trace;
 
 pub use self::core::*;
+#[cfg(feature = "tower")]
+pub use self::tower::from_service;
+pub use around::Next;
+pub use broadcast::*;
+pub use catch_unwind::PanicPayload;
 pub use description::HandlerDescription;
+pub use dynamic_branches::DynamicBranches;
 pub use endpoint::*;
+pub use error::DispatchError;
 pub use filter::*;
 pub use filter_map::*;
+pub use first_of::*;
 pub use inspect::*;
 pub use map::*;
+pub use race::*;
+pub use swappable::SwappableHandler;
+pub use switch::*;
@@ -0,0 +1,151 @@
+use std::{future::Future, ops::ControlFlow};
+
+use crate::{
+    description::NodeKind,
+    handler::{
+        core::{from_fn_with_context, HandlerResult},
+        trace::NodeInfo,
+    },
+    Handler, HandlerDescription,
+};
+
+/// The continuation of a middleware, which executes the wrapped subtree.
+///
+/// See [`Handler::around`].
+#[must_use]
+pub struct Next<'a, Input, Output> {
+    run: Box<dyn FnOnce(Input) -> HandlerResult<'a, Input, Output> + Send + Sync + 'a>,
+}
+
+impl<'a, Input, Output> Next<'a, Input, Output> {
+    /// Executes the wrapped subtree with `container`.
+    pub async fn run(self, container: Input) -> ControlFlow<Output, Input> {
+        (self.run)(container).await
+    }
+}
+
+impl<'a, Input, Output, Descr> Handler<'a, Input, Output, Descr>
+where
+    Input: Send + 'a,
+    Output: 'a,
+    Descr: HandlerDescription,
+{
+    /// Wraps this subtree into `middleware`.
+    ///
+    /// `middleware` receives the container and [`Next`], which executes the
+    /// subtree. It can do some work before and after that, pass another
+    /// container to the subtree (or not execute it at all), and replace the
+    /// result; what it returns becomes the result of this handler. Since the
+    /// subtree calls the continuation of this handler, [`Next::run`] executes
+    /// the handlers chained to this one as well (e.g., `b` in
+    /// `a.around(m).chain(b)`); to wrap a self-contained subtree only, branch
+    /// it instead.
+    ///
+    /// `middleware` is described as a user-defined handler chained to the
+    /// subtree, and it may be a generic `async fn`, so that the same
+    /// middleware can wrap different subtrees.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Instant;
+    ///
+    /// use dptree::{prelude::*, Next};
+    ///
+    /// async fn timed<'a, Output>(
+    ///     container: DependencyMap,
+    ///     next: Next<'a, DependencyMap, Output>,
+    /// ) -> ControlFlow<Output, DependencyMap> {
+    ///     let start = Instant::now();
+    ///     let result = next.run(container).await;
+    ///     println!("Handled in {:?}", start.elapsed());
+    ///     result
+    /// }
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let handler: Handler<_, _> = dptree::entry()
+    ///     .branch(
+    ///         dptree::filter(|user: &str| user == "admin")
+    ///             .endpoint(|| async { "Welcome!" })
+    ///             .around(|container: DependencyMap, next: Next<_, _>| async move {
+    ///                 match next.run(container).await {
+    ///                     ControlFlow::Continue(_) => ControlFlow::Break("Forbidden"),
+    ///                     done => done,
+    ///                 }
+    ///             })
+    ///             .around(timed),
+    ///     );
+    ///
+    /// assert_eq!(handler.dispatch(dptree::deps!["admin"]).await, ControlFlow::Break("Welcome!"));
+    /// assert_eq!(handler.dispatch(dptree::deps!["guest"]).await, ControlFlow::Break("Forbidden"));
+    /// # }
+    /// ```
+    #[must_use]
+    #[track_caller]
+    pub fn around<F, Fut>(self, middleware: F) -> Self
+    where
+        F: Fn(Input, Next<'a, Input, Output>) -> Fut + Send + Sync + 'a,
+        Fut: Future<Output = ControlFlow<Output, Input>> + Send + 'a,
+    {
+        let description = Descr::user_defined().merge_chain(self.description());
+        let node = NodeInfo::new(NodeKind::UserDefined);
+
+        from_fn_with_context(description, Some(node), move |event, cont, ctx| {
+            let this = self.clone();
+            let run = move |event| Box::pin(this.execute_in(event, cont, ctx)) as _;
+            let next = Next { run: Box::new(run) };
+
+            middleware(event, next)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::{
+        deps,
+        di::{DependencyMap, DependencySupplier},
+        endpoint, filter, help_inference,
+    };
+
+    #[tokio::test]
+    async fn around() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+
+        let handler = help_inference(filter(|x: i32| x > 0))
+            .around({
+                let log = Arc::clone(&log);
+                move |container: DependencyMap, next: Next<_, _>| {
+                    let log = Arc::clone(&log);
+                    async move {
+                        log.lock().unwrap().push("before");
+                        let result = next.run(container).await;
+                        log.lock().unwrap().push("after");
+                        result
+                    }
+                }
+            })
+            .chain(endpoint(|x: i32| async move { x }));
+
+        assert_eq!(handler.dispatch(deps![1]).await, ControlFlow::Break(1));
+        assert_eq!(handler.dispatch(deps![-1]).await, ControlFlow::Continue(deps![-1]));
+        assert_eq!(*log.lock().unwrap(), ["before", "after", "before", "after"]);
+
+        // The middleware may change the container and the result.
+        let doubled = help_inference(endpoint(|x: i32| async move { x })).around(
+            |mut container: DependencyMap, next: Next<_, _>| async move {
+                let x: i32 = *container.get();
+                container.insert(x * 2);
+                match next.run(container).await {
+                    ControlFlow::Break(output) => ControlFlow::Break(output + 1),
+                    ControlFlow::Continue(container) => ControlFlow::Continue(container),
+                }
+            },
+        );
+        assert_eq!(doubled.dispatch(deps![5]).await, ControlFlow::Break(11));
+    }
+}
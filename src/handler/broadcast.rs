@@ -0,0 +1,169 @@
+use std::{ops::ControlFlow, sync::Arc};
+
+use futures::future::join_all;
+
+use crate::{
+    description,
+    handler::{context::Context, core::from_fn_with_context},
+    Handler, HandlerDescription,
+};
+
+/// Constructs a handler that dispatches an event to every handler of
+/// `children`, instead of stopping at the first one that breaks.
+///
+/// The outputs of the children are gathered with [`Broadcast::collect`] or
+/// [`Broadcast::fold`]. If at least one child breaks the execution, the
+/// resulting handler breaks it with the gathered value; otherwise, the
+/// execution continues. By default, the children are executed one by one, in
+/// the declaration order; see [`Broadcast::concurrent`].
+///
+/// The description is merged as if the children were branched from
+/// [`crate::entry`]. Since the children produce outputs of another type than
+/// the resulting handler, their errors are not routed to the error handlers
+/// outside of it (see [`Handler::on_error`]).
+///
+/// # Examples
+///
+/// ```
+/// use dptree::prelude::*;
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let subscribers: Vec<Handler<_, _>> = vec![
+///     dptree::endpoint(|x: i32| async move { format!("logged {}", x) }),
+///     dptree::filter(|x: i32| x > 0).endpoint(|| async { "counted".to_owned() }),
+///     dptree::endpoint(|x: i32| async move { format!("stored {}", x) }),
+/// ];
+/// let handler = dptree::broadcast(subscribers).concurrent().collect();
+///
+/// assert_eq!(
+///     handler.dispatch(dptree::deps![-1]).await,
+///     ControlFlow::Break(vec!["logged -1".to_owned(), "stored -1".to_owned()])
+/// );
+/// # }
+/// ```
+#[track_caller]
+pub fn broadcast<'a, Input, Output, Descr, I>(children: I) -> Broadcast<'a, Input, Output, Descr>
+where
+    I: IntoIterator<Item = Handler<'a, Input, Output, Descr>>,
+    Input: Send + 'a,
+    Output: 'a,
+    Descr: HandlerDescription,
+{
+    let children: Vec<_> = children.into_iter().collect();
+    let description = children
+        .iter()
+        .fold(Descr::entry(), |description, child| description.merge_branch(child.description()));
+
+    Broadcast { children: children.into(), description, concurrent: false }
+}
+
+/// A handler under construction, created by [`broadcast`].
+#[must_use]
+pub struct Broadcast<'a, Input, Output, Descr = description::Unspecified> {
+    children: Arc<[Handler<'a, Input, Output, Descr>]>,
+    description: Descr,
+    concurrent: bool,
+}
+
+impl<'a, Input, Output, Descr> Broadcast<'a, Input, Output, Descr>
+where
+    Input: Clone + Send + 'a,
+    Output: Send + 'a,
+    Descr: HandlerDescription,
+{
+    /// Executes the children concurrently.
+    ///
+    /// The outputs are still gathered in the declaration order.
+    pub fn concurrent(self) -> Self {
+        Self { concurrent: true, ..self }
+    }
+
+    /// Collects the outputs of the children into a vector.
+    pub fn collect(self) -> Handler<'a, Input, Vec<Output>, Descr> {
+        self.gather(Vec::new, |mut outputs, output| {
+            outputs.push(output);
+            outputs
+        })
+    }
+
+    /// Reduces the outputs of the children with `f`, starting from `init`.
+    pub fn fold<Acc, F>(self, init: Acc, f: F) -> Handler<'a, Input, Acc, Descr>
+    where
+        Acc: Clone + Send + Sync + 'a,
+        F: Fn(Acc, Output) -> Acc + Send + Sync + 'a,
+    {
+        self.gather(move || init.clone(), f)
+    }
+
+    fn gather<Acc, Init, F>(self, init: Init, f: F) -> Handler<'a, Input, Acc, Descr>
+    where
+        Acc: Send + 'a,
+        Init: Fn() -> Acc + Send + Sync + 'a,
+        F: Fn(Acc, Output) -> Acc + Send + Sync + 'a,
+    {
+        let Self { children, description, concurrent } = self;
+        let (init, f) = (Arc::new(init), Arc::new(f));
+
+        from_fn_with_context(description, None, move |event: Input, cont, ctx| {
+            let children = Arc::clone(&children);
+            let (init, f) = (Arc::clone(&init), Arc::clone(&f));
+
+            async move {
+                // The errors are not routed outside (see `broadcast`).
+                let inner = || Context::new(ctx.env.clone());
+                let results = if concurrent {
+                    join_all(children.iter().map(|child| child.dispatch_in(event.clone(), inner())))
+                        .await
+                } else {
+                    let mut results = Vec::with_capacity(children.len());
+                    for child in children.iter() {
+                        results.push(child.dispatch_in(event.clone(), inner()).await);
+                    }
+                    results
+                };
+
+                let mut acc = None;
+                for result in results {
+                    if let ControlFlow::Break(output) = result {
+                        acc = Some(f(acc.unwrap_or_else(|| init()), output));
+                    }
+                }
+
+                match acc {
+                    Some(acc) => ControlFlow::Break(acc),
+                    None => cont(event).await,
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{deps, di::DependencyMap, endpoint, filter, help_inference};
+
+    #[tokio::test]
+    async fn broadcast() {
+        let children = || -> Vec<Handler<DependencyMap, i32>> {
+            vec![
+                filter(|x: i32| x > 0).endpoint(|x: i32| async move { x }),
+                endpoint(|x: i32| async move { x * 10 }),
+                filter(|x: i32| x > 0).endpoint(|x: i32| async move { x * 100 }),
+            ]
+        };
+
+        let sum = super::broadcast(children()).fold(0, |acc, x| acc + x);
+        assert_eq!(sum.dispatch(deps![1]).await, ControlFlow::Break(111));
+        assert_eq!(sum.dispatch(deps![-1]).await, ControlFlow::Break(-10));
+
+        let all = super::broadcast(children()).concurrent().collect();
+        assert_eq!(all.dispatch(deps![2]).await, ControlFlow::Break(vec![2, 20, 200]));
+
+        let none =
+            super::broadcast(vec![help_inference(filter(|x: i32| x > 0)).endpoint(|| async {})])
+                .collect();
+        assert_eq!(none.dispatch(deps![-1]).await, ControlFlow::Continue(deps![-1]));
+    }
+}
@@ -0,0 +1,259 @@
+use std::{
+    any::Any,
+    fmt::{Debug, Formatter},
+    future::Future,
+    ops::ControlFlow,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use futures::future::poll_fn;
+
+use crate::{
+    di::Insert,
+    handler::{context::Context, core::from_fn_with_context},
+    Handler, HandlerDescription,
+};
+
+/// The payload of a panic caught by [`Handler::catch_unwind`].
+///
+/// This type is inserted into the container passed to the fallback handler,
+/// so it can be injected.
+#[derive(Clone)]
+pub struct PanicPayload {
+    message: Option<String>,
+    payload: Arc<Mutex<Option<Box<dyn Any + Send>>>>,
+}
+
+impl PanicPayload {
+    fn new(payload: Box<dyn Any + Send>) -> Self {
+        let message = match payload.downcast_ref::<&'static str>() {
+            Some(message) => Some((*message).to_owned()),
+            None => payload.downcast_ref::<String>().cloned(),
+        };
+
+        Self { message, payload: Arc::new(Mutex::new(Some(payload))) }
+    }
+
+    /// Returns the panic message, if the panic was caused by [`panic!`] with a
+    /// message.
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+
+    /// Takes the original payload, e.g., for [`std::panic::resume_unwind`].
+    ///
+    /// Returns `None` if the payload has already been taken from this value or
+    /// its clones.
+    pub fn take(&self) -> Option<Box<dyn Any + Send>> {
+        self.payload.lock().unwrap_or_else(|error| error.into_inner()).take()
+    }
+}
+
+impl Debug for PanicPayload {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PanicPayload").field("message", &self.message).finish()
+    }
+}
+
+/// A panic that has happened in the continuation of the subtree executed with
+/// the identifier `id`, so it must not be caught by that subtree.
+struct ContinuationPanic {
+    id: usize,
+    payload: Box<dyn Any + Send>,
+}
+
+/// Polls `fut`, catching the panics.
+async fn catching<Fut: Future>(fut: Fut) -> Result<Fut::Output, Box<dyn Any + Send>> {
+    futures::pin_mut!(fut);
+
+    poll_fn(|cx| match panic::catch_unwind(AssertUnwindSafe(|| fut.as_mut().poll(cx))) {
+        Ok(poll) => poll.map(Ok),
+        Err(payload) => std::task::Poll::Ready(Err(payload)),
+    })
+    .await
+}
+
+impl<'a, Input, Output, Descr> Handler<'a, Input, Output, Descr>
+where
+    Input: Send + 'a,
+    Output: Send + 'a,
+    Descr: HandlerDescription,
+{
+    /// Catches the panics that happen in this subtree, passing them to
+    /// `fallback`.
+    ///
+    /// Both the panics in injected functions and in their futures are caught,
+    /// including those caused by missing dependencies. When a panic is caught,
+    /// its [`PanicPayload`] is inserted into the container that was passed to
+    /// this handler, and the container is dispatched to `fallback`; its result
+    /// becomes the result of this subtree. The handlers in the continuation of
+    /// this handler (e.g., `b` in `a.catch_unwind(f).chain(b)`) do not belong
+    /// to the subtree, so their panics are propagated.
+    ///
+    /// See also: [`Handler::catch_unwind_with`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dptree::{prelude::*, PanicPayload};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let handler: Handler<_, _> = dptree::endpoint(|x: i32| async move { 100 / x })
+    ///     .catch_unwind(dptree::endpoint(|payload: PanicPayload| async move {
+    ///         assert_eq!(payload.message(), Some("attempt to divide by zero"));
+    ///         0
+    ///     }));
+    ///
+    /// assert_eq!(handler.dispatch(dptree::deps![0]).await, ControlFlow::Break(0));
+    /// # }
+    /// ```
+    #[must_use]
+    #[track_caller]
+    pub fn catch_unwind(self, fallback: Self) -> Self
+    where
+        Input: Insert<PanicPayload> + Clone,
+    {
+        let description = self.description().catch_unwind(
+            fallback.description(),
+            <Input as Insert<PanicPayload>>::inserted_type().into_iter().collect(),
+        );
+
+        self.catch_unwind_impl(description, Input::clone, move |mut container, payload, ctx| {
+            let fallback = fallback.clone();
+
+            async move {
+                container.insert(PanicPayload::new(payload));
+                fallback.dispatch_in(container, ctx).await
+            }
+        })
+    }
+
+    /// Catches the panics that happen in this subtree, breaking the execution
+    /// with the output of `f`.
+    ///
+    /// See [`Handler::catch_unwind`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dptree::prelude::*;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let handler: Handler<_, _> = dptree::endpoint(|x: i32| async move { Ok(100 / x) })
+    ///     .catch_unwind_with(|payload| Err(payload.message().map(ToOwned::to_owned)));
+    ///
+    /// assert_eq!(handler.dispatch(dptree::deps![10]).await, ControlFlow::Break(Ok(10)));
+    /// assert!(matches!(handler.dispatch(dptree::deps![0]).await, ControlFlow::Break(Err(Some(_)))));
+    /// # }
+    /// ```
+    #[must_use]
+    #[track_caller]
+    pub fn catch_unwind_with<F>(self, f: F) -> Self
+    where
+        F: Fn(PanicPayload) -> Output + Send + Sync + 'a,
+    {
+        let description = self.description().catch_unwind(&Descr::endpoint(), Vec::new());
+        let f = Arc::new(f);
+
+        self.catch_unwind_impl(
+            description,
+            |_| (),
+            move |(), payload, _| {
+                let output = f(PanicPayload::new(payload));
+                async move { ControlFlow::Break(output) }
+            },
+        )
+    }
+
+    /// Constructs a handler that executes this one, calling `on_panic` with the
+    /// result of `backup` (obtained before the execution) if it panics, along
+    /// with the context of this handler.
+    fn catch_unwind_impl<Backup, F, Fut>(
+        self,
+        description: Descr,
+        backup: fn(&Input) -> Backup,
+        on_panic: F,
+    ) -> Self
+    where
+        Backup: Send + 'a,
+        F: Fn(Backup, Box<dyn Any + Send>, Context<'a, Input, Output>) -> Fut + Send + Sync + 'a,
+        Fut: Future<Output = ControlFlow<Output, Input>> + Send + 'a,
+    {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+        let on_panic = Arc::new(on_panic);
+
+        from_fn_with_context(description, None, move |event: Input, cont, ctx| {
+            let this = self.clone();
+            let on_panic = Arc::clone(&on_panic);
+
+            async move {
+                let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+                let backup = backup(&event);
+
+                let cont = move |event| async move {
+                    match catching(cont(event)).await {
+                        Ok(result) => result,
+                        Err(payload) => {
+                            panic::resume_unwind(Box::new(ContinuationPanic { id, payload }))
+                        }
+                    }
+                };
+
+                match catching(this.execute_in(event, cont, ctx.clone())).await {
+                    Ok(result) => result,
+                    Err(payload) => match payload.downcast::<ContinuationPanic>() {
+                        Ok(panic) if panic.id == id => panic::resume_unwind(panic.payload),
+                        Ok(panic) => panic::resume_unwind(panic),
+                        Err(payload) => on_panic(backup, payload, ctx).await,
+                    },
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::ControlFlow;
+
+    use super::*;
+    use crate::{deps, di::DependencyMap, endpoint, entry, filter, help_inference};
+
+    #[tokio::test]
+    async fn catch_unwind() {
+        let handler = help_inference(entry())
+            .branch(filter(|x: i32| x == 1).endpoint(|| async { panic!("in the future") }))
+            .branch(filter(|x: i32| x == 2).endpoint(|_: String| async { unreachable!() }))
+            .catch_unwind(endpoint(|payload: PanicPayload| async move {
+                payload.message().unwrap().to_owned()
+            }));
+
+        assert_eq!(
+            handler.dispatch(deps![1]).await,
+            ControlFlow::Break("in the future".to_owned())
+        );
+        match handler.dispatch(deps![2]).await {
+            ControlFlow::Break(message) => assert!(message.contains("String was requested")),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+    }
+
+    #[tokio::test]
+    async fn continuation_panic() {
+        let handler: Handler<'static, DependencyMap, ()> = entry()
+            .chain(entry().catch_unwind_with(|_| unreachable!()))
+            .endpoint(|| async { panic!("in the continuation") });
+
+        let error = tokio::spawn(async move { handler.dispatch(deps![]).await })
+            .await
+            .expect_err("The panic must be propagated");
+        assert_eq!(*error.into_panic().downcast::<&str>().unwrap(), "in the continuation");
+    }
+}
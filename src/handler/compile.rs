@@ -0,0 +1,428 @@
+//! Flattening handler trees into programs.
+//!
+//! See [`crate::Handler::compile`].
+
+use std::{
+    ops::ControlFlow,
+    sync::{Arc, Mutex},
+};
+
+use futures::future::BoxFuture;
+
+use crate::{
+    handler::{
+        context::{Context, Env},
+        core::{Cont, HandlerResult},
+        on_error,
+        spans::Spans,
+        trace::{self, NodeInfo},
+    },
+    Handler, HandlerDescription,
+};
+
+/// A decision made by a built-in handler, such as [`crate::filter`].
+pub(crate) enum Step<Output, Input> {
+    /// Pass `Input` to the continuation.
+    Pass(Input),
+
+    /// Pass `next` to the continuation; if it continues the execution, continue
+    /// it with `restore` instead of its input.
+    Scoped { next: Input, restore: Input },
+
+    /// Continue the execution without calling the continuation.
+    Reject(Input),
+
+    /// Break the execution.
+    Break(Output),
+
+    /// Continue the execution without calling the continuation, since the
+    /// dispatch has failed (see [`Env::fail`]).
+    Abort(Input),
+
+    /// Route `failed`, a copy of `event` into which an error of the type
+    /// `type_name` has been inserted, to the error handler (see
+    /// [`crate::Handler::on_error`]); if it continues the execution, continue
+    /// it with `event` without calling the continuation.
+    Fail { event: Input, failed: Input, type_name: &'static str },
+}
+
+pub(crate) type StepFn<'a, Input, Output> =
+    dyn Fn(Input, Env) -> BoxFuture<'a, Step<Output, Input>> + Send + Sync + 'a;
+
+/// The structure of a handler.
+pub(crate) enum Op<'a, Input, Output, Descr> {
+    /// A handler that can only be executed as a whole, such as a user-defined
+    /// one.
+    Opaque,
+
+    /// A built-in handler, constructed with
+    /// [`from_step`](crate::handler::core::from_step).
+    Step(NodeInfo, Arc<StepFn<'a, Input, Output>>),
+
+    /// `a.chain(b)`.
+    Chain(Handler<'a, Input, Output, Descr>, Handler<'a, Input, Output, Descr>),
+
+    /// `a.branch(b).branch(c)...` or, without `a`, [`crate::first_of`].
+    Branch(Option<Handler<'a, Input, Output, Descr>>, Vec<Handler<'a, Input, Output, Descr>>),
+}
+
+impl<'a, Input, Output, Descr> Op<'a, Input, Output, Descr> {
+    /// Takes the handlers combined by this one, leaving [`Op::Opaque`].
+    pub(crate) fn take_children(&mut self) -> Vec<Handler<'a, Input, Output, Descr>> {
+        match std::mem::replace(self, Op::Opaque) {
+            Op::Opaque | Op::Step(..) => Vec::new(),
+            Op::Chain(first, second) => vec![first, second],
+            Op::Branch(head, mut children) => {
+                children.extend(head);
+                children
+            }
+        }
+    }
+}
+
+pub(crate) enum Instruction<'a, Input, Output, Descr> {
+    /// Executes a built-in handler.
+    Step(NodeInfo, Arc<StepFn<'a, Input, Output>>),
+
+    /// Executes a handler with the rest of the program as its continuation.
+    Opaque(Handler<'a, Input, Output, Descr>),
+
+    /// Starts a branch, `child`; if it continues the execution, the execution
+    /// is resumed at the instruction `resume`.
+    Enter { resume: usize, child: Handler<'a, Input, Output, Descr> },
+
+    /// Finishes a branch, continuing the execution.
+    Exit,
+
+    /// Calls the continuation of the compiled handler.
+    End,
+}
+
+/// An entry of the stack of the interpreter.
+enum Frame<Input> {
+    /// Set by [`Step::Scoped`].
+    Restore(Input),
+
+    /// Set by [`Instruction::Enter`].
+    Resume(usize),
+
+    /// Set by a built-in handler that has passed its input further, if
+    /// [`Spans::ENABLED`].
+    Close,
+}
+
+pub(crate) type Program<'a, Input, Output, Descr> = Arc<[Instruction<'a, Input, Output, Descr>]>;
+
+/// The continuation of a compiled handler, which is called by
+/// [`Instruction::End`] at most once.
+type Terminal<'a, Input, Output> = Arc<Mutex<Option<Cont<'a, Input, Output>>>>;
+
+impl<'a, Input, Output, Descr> Handler<'a, Input, Output, Descr>
+where
+    Input: Send + 'a,
+    Output: 'a,
+    Descr: HandlerDescription,
+{
+    /// Compiles this handler ahead of its first execution.
+    ///
+    /// The handlers combined with [`Handler::chain`], [`Handler::branch`],
+    /// [`Handler::branch_many`], and [`crate::first_of`] are not executed by
+    /// nesting the combined handlers, but are lowered into flat programs,
+    /// which are executed in a loop: the built-in handlers (such as
+    /// [`crate::filter`], [`crate::map`], [`crate::endpoint`], and the
+    /// combinations of them) are flattened, while the others (e.g.,
+    /// user-defined or [`Handler::named`] handlers) are executed as a whole,
+    /// with the rest of the program as their continuation. Thus, the
+    /// allocations per dispatch and the stack depth depend mostly on the
+    /// number of handlers actually executed, not on the shape of the tree.
+    ///
+    /// A program is compiled on the first execution of a handler and cached;
+    /// this method compiles it right away, so that the first dispatch is not
+    /// slower than the others. The resulting handler is this handler itself.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dptree::prelude::*;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let handler: Handler<_, _> = (0..100)
+    ///     .fold(dptree::entry(), |handler, i| {
+    ///         handler.branch(dptree::filter(move |x: i32| x == i).endpoint(move || async move { i * 2 }))
+    ///     })
+    ///     .compile();
+    ///
+    /// assert_eq!(handler.dispatch(dptree::deps![21]).await, ControlFlow::Break(42));
+    /// assert_eq!(handler.dispatch(dptree::deps![100]).await, ControlFlow::Continue(dptree::deps![100]));
+    /// # }
+    /// ```
+    #[must_use]
+    pub fn compile(&self) -> Self {
+        if let Op::Chain(..) | Op::Branch(..) = self.op() {
+            self.program();
+        }
+        self.clone()
+    }
+}
+
+/// Lowers `handler` into a flat program.
+pub(crate) fn lower<'a, Input, Output, Descr>(
+    handler: &Handler<'a, Input, Output, Descr>,
+) -> Program<'a, Input, Output, Descr>
+where
+    Input: Send + 'a,
+    Output: 'a,
+    Descr: HandlerDescription,
+{
+    enum Task<'a, Input, Output, Descr> {
+        Emit(Handler<'a, Input, Output, Descr>),
+        Enter(Handler<'a, Input, Output, Descr>),
+        Exit,
+    }
+
+    let mut instructions = Vec::new();
+    let mut entered = Vec::new();
+    let mut tasks = vec![Task::Emit(handler.clone())];
+
+    // The tree is traversed without recursion, since it may be deep.
+    while let Some(task) = tasks.pop() {
+        match task {
+            Task::Emit(handler) => match handler.op() {
+                Op::Opaque => instructions.push(Instruction::Opaque(handler.clone())),
+                Op::Step(node, step) => {
+                    instructions.push(Instruction::Step(*node, Arc::clone(step)))
+                }
+                Op::Chain(first, second) => {
+                    tasks.push(Task::Emit(second.clone()));
+                    tasks.push(Task::Emit(first.clone()));
+                }
+                Op::Branch(head, children) => {
+                    for child in children.iter().rev() {
+                        tasks.push(Task::Exit);
+                        tasks.push(Task::Emit(child.clone()));
+                        tasks.push(Task::Enter(child.clone()));
+                    }
+                    if let Some(head) = head {
+                        tasks.push(Task::Emit(head.clone()));
+                    }
+                }
+            },
+            Task::Enter(child) => {
+                entered.push(instructions.len());
+                instructions.push(Instruction::Enter { resume: 0, child });
+            }
+            Task::Exit => {
+                instructions.push(Instruction::Exit);
+                let enter = entered.pop().expect("Every exit has a matching enter");
+                let len = instructions.len();
+                if let Instruction::Enter { resume, .. } = &mut instructions[enter] {
+                    *resume = len;
+                }
+            }
+        }
+    }
+    instructions.push(Instruction::End);
+
+    instructions.into()
+}
+
+/// Executes `program` in the context `ctx` with `cont` as its continuation.
+pub(crate) fn run<'a, Input, Output, Descr>(
+    program: Program<'a, Input, Output, Descr>,
+    event: Input,
+    cont: Cont<'a, Input, Output>,
+    ctx: Context<'a, Input, Output>,
+) -> HandlerResult<'a, Input, Output>
+where
+    Input: Send + 'a,
+    Output: 'a,
+    Descr: HandlerDescription,
+{
+    resume(program, 0, Arc::new(Mutex::new(Some(cont))), ctx, event)
+}
+
+/// [`interpret`], boxed to allow recursion.
+fn resume<'a, Input, Output, Descr>(
+    program: Program<'a, Input, Output, Descr>,
+    pc: usize,
+    terminal: Terminal<'a, Input, Output>,
+    ctx: Context<'a, Input, Output>,
+    event: Input,
+) -> HandlerResult<'a, Input, Output>
+where
+    Input: Send + 'a,
+    Output: 'a,
+    Descr: HandlerDescription,
+{
+    Box::pin(interpret(program, pc, terminal, ctx, event))
+}
+
+/// Executes `program` from the instruction `pc`.
+///
+/// Returns once the execution is broken, or continued past the beginning of
+/// the current branch (or `pc`, if it is not in a branch). If the dispatch has
+/// failed, the execution is continued without executing anything else.
+async fn interpret<'a, Input, Output, Descr>(
+    program: Program<'a, Input, Output, Descr>,
+    mut pc: usize,
+    terminal: Terminal<'a, Input, Output>,
+    ctx: Context<'a, Input, Output>,
+    mut event: Input,
+) -> ControlFlow<Output, Input>
+where
+    Input: Send + 'a,
+    Output: 'a,
+    Descr: HandlerDescription,
+{
+    let mut frames = Vec::new();
+    let mut spans = Spans::new();
+
+    loop {
+        if ctx.env.is_aborted() {
+            while let Some(frame) = frames.pop() {
+                match frame {
+                    Frame::Restore(restore) => event = restore,
+                    Frame::Resume(_) => {}
+                    Frame::Close => spans.close(),
+                }
+            }
+            return ControlFlow::Continue(event);
+        }
+
+        let result = match &program[pc] {
+            Instruction::Step(node, step) => {
+                trace::entered(&ctx.env, *node);
+
+                // The step is not kept across the routing of an error, since
+                // `Output` may not be `Send`.
+                let (event, failure) = match spans.step(*node, step(event, ctx.env.clone())).await {
+                    Step::Pass(next) => {
+                        if Spans::ENABLED {
+                            frames.push(Frame::Close);
+                        }
+                        event = next;
+                        pc += 1;
+                        continue;
+                    }
+                    Step::Scoped { next, restore } => {
+                        frames.push(Frame::Restore(restore));
+                        if Spans::ENABLED {
+                            frames.push(Frame::Close);
+                        }
+                        event = next;
+                        pc += 1;
+                        continue;
+                    }
+                    Step::Reject(event) | Step::Abort(event) => (event, None),
+                    Step::Break(output) => {
+                        spans.break_all();
+                        return ControlFlow::Break(output);
+                    }
+                    Step::Fail { event, failed, type_name } => (event, Some((failed, type_name))),
+                };
+
+                match failure {
+                    Some((failed, type_name)) => {
+                        on_error::route(&ctx, event, failed, type_name).await
+                    }
+                    None => ControlFlow::Continue(event),
+                }
+            }
+            Instruction::Opaque(handler) => {
+                let rest = Arc::clone(&program);
+                let terminal = Arc::clone(&terminal);
+                let outer = ctx.clone();
+                let cont = move |event| resume(rest, pc + 1, terminal, outer, event);
+
+                spans.instrument(handler.clone().execute_in(event, cont, ctx.clone())).await
+            }
+            Instruction::Enter { resume, child } => {
+                if ctx.env.skip.skips(child.description()) {
+                    pc = *resume;
+                } else {
+                    frames.push(Frame::Resume(*resume));
+                    pc += 1;
+                }
+                continue;
+            }
+            Instruction::Exit => ControlFlow::Continue(event),
+            Instruction::End => {
+                let cont = terminal
+                    .lock()
+                    .unwrap_or_else(|error| error.into_inner())
+                    .take()
+                    .expect("The end of a program is reached at most once");
+                spans.instrument(cont(event)).await
+            }
+        };
+
+        // The execution is continued: go to the beginning of the current
+        // branch, restoring the input of the scoped handlers on the way.
+        event = match result {
+            ControlFlow::Continue(event) => event,
+            ControlFlow::Break(output) => {
+                spans.break_all();
+                return ControlFlow::Break(output);
+            }
+        };
+        loop {
+            match frames.pop() {
+                Some(Frame::Restore(restore)) => event = restore,
+                Some(Frame::Resume(resume)) => {
+                    pc = resume;
+                    break;
+                }
+                Some(Frame::Close) => spans.close(),
+                None => return ControlFlow::Continue(event),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{deps, endpoint, entry, filter, filter_map, from_fn, help_inference, map};
+
+    #[tokio::test]
+    async fn compile() {
+        let handler = help_inference(filter_map(|x: i32| x.checked_mul(2)))
+            .branch(filter(|x: i32| x > 10).endpoint(|x: i32| async move { format!("big {}", x) }))
+            .branch(
+                map(|x: i32| x + 1)
+                    .chain(filter(|x: i32| x % 2 == 0))
+                    .endpoint(|| async { unreachable!() }),
+            )
+            .chain(from_fn(|event, cont| async move {
+                match cont(event).await {
+                    ControlFlow::Continue(event) => ControlFlow::Continue(event),
+                    ControlFlow::Break(output) => ControlFlow::Break(format!("{}!", output)),
+                }
+            }))
+            .branch(
+                entry()
+                    .chain(filter(|x: i32| x < 0))
+                    .endpoint(|x: i32| async move { format!("negative {}", x) }),
+            )
+            .branch(endpoint(|x: i32| async move { format!("small {}", x) }));
+        let compiled = handler.compile();
+
+        let expected = [
+            (6, ControlFlow::Break("big 12".to_owned())),
+            (-3, ControlFlow::Break("negative -6!".to_owned())),
+            (2, ControlFlow::Break("small 4!".to_owned())),
+            (i32::MAX, ControlFlow::Continue(deps![i32::MAX])),
+        ];
+        for (x, expected) in expected.iter() {
+            assert_eq!(&compiled.dispatch(deps![*x]).await, expected, "x = {}", x);
+        }
+
+        // A compiled handler continues the execution of a larger tree.
+        let outer = entry().branch(compiled).branch(endpoint(|| async { "overflow".to_owned() }));
+        assert_eq!(
+            outer.dispatch(deps![i32::MAX]).await,
+            ControlFlow::Break("overflow".to_owned())
+        );
+    }
+}
@@ -0,0 +1,109 @@
+//! The state of a dispatch, which is passed down a handler tree along with the
+//! events.
+
+use std::sync::{Arc, Mutex};
+
+use crate::{
+    handler::{
+        error::{DispatchError, Failure},
+        kinds::Skip,
+        observer::Observers,
+        on_error::Route,
+        trace::Trace,
+    },
+    names::{self, Path},
+};
+
+/// The context in which a handler is executed.
+///
+/// The handlers that execute other handlers pass their context to them, and
+/// the continuations capture the context of the handlers that have constructed
+/// them, since they do not belong to the same subtree. A dispatch that is not
+/// started by a built-in handler (e.g., [`crate::Handler::dispatch`] called by
+/// a user-defined handler) starts with a new context.
+pub(crate) struct Context<'a, Input, Output> {
+    /// The error handler of the subtree that is being executed, if any.
+    pub(crate) route: Route<'a, Input, Output>,
+
+    /// The part of the context that does not depend on the types of the
+    /// handlers.
+    pub(crate) env: Env,
+}
+
+impl<'a, Input, Output> Context<'a, Input, Output> {
+    /// A context with no error handler.
+    pub(crate) fn new(env: Env) -> Self {
+        Self { route: None, env }
+    }
+}
+
+// `#[derive(Clone)]` would require `Input: Clone` and `Output: Clone`.
+impl<'a, Input, Output> Clone for Context<'a, Input, Output> {
+    fn clone(&self) -> Self {
+        Self { route: self.route.clone(), env: self.env.clone() }
+    }
+}
+
+impl<'a, Input, Output> Default for Context<'a, Input, Output> {
+    fn default() -> Self {
+        Self::new(Env::default())
+    }
+}
+
+/// The part of [`Context`] that does not depend on the types of the handlers.
+#[derive(Clone, Default)]
+pub(crate) struct Env {
+    /// Set by [`crate::Handler::try_dispatch`].
+    failure: Option<Arc<Failure>>,
+
+    /// Set by [`crate::Handler::dispatch_traced`].
+    pub(crate) trace: Option<Arc<Mutex<Trace>>>,
+
+    /// Extended by [`crate::Handler::observe`].
+    pub(crate) observers: Observers,
+
+    /// Extended by [`crate::Handler::named`]; `None` outside of the named
+    /// subtrees.
+    pub(crate) path: Option<Arc<Path>>,
+
+    /// Set by [`crate::Handler::dispatch_with_kind`].
+    pub(crate) skip: Skip,
+}
+
+impl Env {
+    /// An environment of [`crate::Handler::try_dispatch`], which fails with
+    /// the error recorded into `failure`.
+    pub(crate) fn failing_into(failure: Arc<Failure>) -> Self {
+        Self { failure: Some(failure), ..Self::default() }
+    }
+
+    /// An environment of [`crate::Handler::dispatch_traced`], which records
+    /// the events into `trace`.
+    pub(crate) fn tracing_into(trace: Arc<Mutex<Trace>>) -> Self {
+        Self { trace: Some(trace), ..Self::default() }
+    }
+
+    /// An environment of [`crate::Handler::dispatch_with_kind`], in which the
+    /// branches are skipped according to `skip`.
+    pub(crate) fn skipping(skip: Skip) -> Self {
+        Self { skip, ..Self::default() }
+    }
+
+    /// Fails the dispatch with `error`.
+    ///
+    /// In [`crate::Handler::try_dispatch`], the error is recorded, and the
+    /// dispatch is [aborted](Env::is_aborted); otherwise, this function
+    /// panics.
+    pub(crate) fn fail(&self, error: DispatchError) {
+        match &self.failure {
+            Some(failure) => failure.set(error),
+            None => panic!("{}", names::describe(self.path.as_deref(), &error)),
+        }
+    }
+
+    /// Whether the dispatch has failed, so that the remaining handlers must
+    /// not be executed.
+    pub(crate) fn is_aborted(&self) -> bool {
+        self.failure.as_ref().is_some_and(|failure| failure.is_set())
+    }
+}
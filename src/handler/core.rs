@@ -1,8 +1,24 @@
-use std::{future::Future, ops::ControlFlow, sync::Arc};
-
-use futures::future::BoxFuture;
-
-use crate::{description, HandlerDescription};
+use std::{
+    fmt::{Debug, Formatter},
+    future::Future,
+    ops::ControlFlow,
+    sync::{Arc, Mutex, OnceLock},
+    task::Poll,
+};
+
+use futures::future::{poll_fn, BoxFuture};
+
+use crate::{
+    description::{self, NodeKind},
+    handler::{
+        compile::{self, Op, Program, Step},
+        context::{Context, Env},
+        error::Failure,
+        on_error, spans,
+        trace::{self, NodeInfo, Trace},
+    },
+    DispatchError, HandlerDescription,
+};
 
 /// An instance that receives an input and decides whether to break a chain or
 /// pass the value further.
@@ -45,16 +61,48 @@ use crate::{description, HandlerDescription};
 /// whether or not to call `c`, but when it is branched, whether `c` is called
 /// depends solely on `a`.
 pub struct Handler<'a, Input, Output, Descr = description::Unspecified> {
-    data: Arc<HandlerData<Descr, DynF<'a, Input, Output>>>,
+    data: Arc<HandlerData<'a, Input, Output, Descr, DynF<'a, Input, Output>>>,
 }
 
-struct HandlerData<Descr, F: ?Sized> {
+struct HandlerData<'a, Input, Output, Descr, F: ?Sized> {
     description: Descr,
+    /// `None` for the handlers that only combine other handlers, such as
+    /// [`Handler::chain`].
+    node: Option<NodeInfo>,
+    /// Set by [`Handler::named`].
+    name: Option<&'static str>,
+    /// The structure of this handler, for [`Handler::compile`].
+    op: Op<'a, Input, Output, Descr>,
+    /// The program executing [`Op::Chain`] and [`Op::Branch`], compiled on
+    /// demand.
+    program: OnceLock<Program<'a, Input, Output, Descr>>,
+    /// Not called for [`Op::Chain`] and [`Op::Branch`].
     f: F,
 }
 
-type DynF<'a, Input, Output> =
-    dyn Fn(Input, Cont<'a, Input, Output>) -> HandlerResult<'a, Input, Output> + Send + Sync + 'a;
+// Handlers may be nested arbitrarily deep, so they are dismantled without
+// recursion: the combined handlers of a uniquely owned handler are moved out
+// of it before it is dropped.
+impl<'a, Input, Output, Descr, F: ?Sized> Drop for HandlerData<'a, Input, Output, Descr, F> {
+    fn drop(&mut self) {
+        let mut pending = self.op.take_children();
+
+        while let Some(mut handler) = pending.pop() {
+            if let Some(data) = Arc::get_mut(&mut handler.data) {
+                pending.append(&mut data.op.take_children());
+            }
+        }
+    }
+}
+
+type DynF<'a, Input, Output> = dyn Fn(
+        Input,
+        Cont<'a, Input, Output>,
+        Context<'a, Input, Output>,
+    ) -> HandlerResult<'a, Input, Output>
+    + Send
+    + Sync
+    + 'a;
 
 /// A continuation representing the rest of a handler chain.
 pub type Cont<'a, Input, Output> =
@@ -71,6 +119,19 @@ impl<'a, Input, Output, Descr> Clone for Handler<'a, Input, Output, Descr> {
     }
 }
 
+impl<'a, Input, Output, Descr> Debug for Handler<'a, Input, Output, Descr>
+where
+    Descr: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Handler")
+            .field("name", &self.data.name)
+            .field("node", &self.data.node)
+            .field("description", &self.data.description)
+            .finish()
+    }
+}
+
 impl<'a, Input, Output, Descr> Handler<'a, Input, Output, Descr>
 where
     Input: Send + 'a,
@@ -108,12 +169,7 @@ where
     pub fn chain(self, next: Self) -> Self {
         let required_update_kinds_set = self.description().merge_chain(next.description());
 
-        from_fn_with_description(required_update_kinds_set, move |event, cont| {
-            let this = self.clone();
-            let next = next.clone();
-
-            this.execute(event, |event| next.execute(event, cont))
-        })
+        from_op(required_update_kinds_set, Op::Chain(self, next))
     }
 
     /// Chain two handlers to make a tree of responsibility.
@@ -159,16 +215,56 @@ where
     {
         let required_update_kinds_set = self.description().merge_branch(next.description());
 
-        from_fn_with_description(required_update_kinds_set, move |event, cont| {
-            let this = self.clone();
-            let next = next.clone();
+        from_op(required_update_kinds_set, Op::Branch(Some(self), vec![next]))
+    }
 
-            this.execute(event, |event| async move {
-                match next.dispatch(event).await {
-                    ControlFlow::Continue(event) => cont(event).await,
-                    done => done,
-                }
-            })
+    /// Gives a human-readable name to this handler.
+    ///
+    /// The name is used in diagnostics: the panics caused by missing
+    /// dependencies mention the names of the subtrees in which they have
+    /// occurred (e.g., ``In `purchase_flow > payment`: ...``),
+    /// [`Handler::dispatch_traced`] records when a named subtree is entered,
+    /// [`Structure`] keeps the name (so [`Handler::to_dot`] groups the
+    /// subtree into a cluster), and the [`Debug`] output includes it. The
+    /// description is derived with [`HandlerDescription::named`].
+    ///
+    /// The handlers in the continuation of this handler (e.g., `b` in
+    /// `a.named("a").chain(b)`) do not belong to the named subtree.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dptree::prelude::*;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// use dptree::trace::TraceEvent;
+    ///
+    /// let handler: Handler<_, _> = dptree::entry()
+    ///     .branch(dptree::filter(|x: i32| x > 0).endpoint(|| async { "positive" }).named("positive"))
+    ///     .named("root");
+    ///
+    /// let (_, trace) = handler.dispatch_traced(dptree::deps![1]).await;
+    /// assert_eq!(trace.events[0], TraceEvent::Named("root"));
+    /// # }
+    /// ```
+    ///
+    /// [`Structure`]: crate::description::Structure
+    /// [`Handler::to_dot`]: #method.to_dot
+    #[must_use]
+    #[track_caller]
+    pub fn named(self, name: &'static str) -> Self {
+        let description = self.description().named(name);
+
+        from_fn_with_data(description, None, Some(name), Op::Opaque, move |event, cont, mut ctx| {
+            trace::named(&ctx.env, name);
+
+            // The continuation keeps the context in which it has been
+            // constructed, outside of this subtree.
+            let path = ctx.env.path.as_deref().cloned().unwrap_or_default().join(name);
+            ctx.env.path = Some(Arc::new(path));
+
+            self.clone().execute_in(event, cont, ctx)
         })
     }
 
@@ -202,7 +298,38 @@ where
         Cont: Send + Sync + 'a,
         ContFut: Future<Output = ControlFlow<Output, Input>> + Send + 'a,
     {
-        (self.data.f)(container, Box::new(|event| Box::pin(cont(event)))).await
+        self.execute_in(container, cont, Context::default()).await
+    }
+
+    /// [`Handler::execute`] in the context `ctx` of an outer dispatch.
+    pub(crate) async fn execute_in<Cont, ContFut>(
+        self,
+        container: Input,
+        cont: Cont,
+        ctx: Context<'a, Input, Output>,
+    ) -> ControlFlow<Output, Input>
+    where
+        Cont: FnOnce(Input) -> ContFut,
+        Cont: Send + Sync + 'a,
+        ContFut: Future<Output = ControlFlow<Output, Input>> + Send + 'a,
+    {
+        let cont: self::Cont<'a, Input, Output> = Box::new(|event| Box::pin(cont(event)));
+
+        if let Op::Chain(..) | Op::Branch(..) = self.data.op {
+            return compile::run(self.program(), container, cont, ctx).await;
+        }
+
+        if let Some(node) = self.data.node {
+            trace::entered(&ctx.env, node);
+        }
+
+        let f = &self.data.f;
+        let execute = move || f(container, cont, ctx);
+
+        match self.data.node {
+            Some(node) => spans::handler(node, execute).await,
+            None => execute().await,
+        }
     }
 
     /// Executes this handler.
@@ -210,13 +337,126 @@ where
     /// Returns [`ControlFlow::Break`] when executed successfully,
     /// [`ControlFlow::Continue`] otherwise.
     pub async fn dispatch(&self, container: Input) -> ControlFlow<Output, Input> {
-        self.clone().execute(container, |event| async move { ControlFlow::Continue(event) }).await
+        self.dispatch_in(container, Context::default()).await
+    }
+
+    /// [`Handler::dispatch`] in the context `ctx` of an outer dispatch.
+    pub(crate) async fn dispatch_in(
+        &self,
+        container: Input,
+        ctx: Context<'a, Input, Output>,
+    ) -> ControlFlow<Output, Input> {
+        let cont = |event| async move { ControlFlow::Continue(event) };
+        self.clone().execute_in(container, cont, ctx).await
+    }
+
+    /// The panic-free version of [`Handler::dispatch`].
+    ///
+    /// If some handler requests a dependency that is missing from the input
+    /// container, the dispatch is stopped and [`DispatchError`] is returned,
+    /// instead of panicking: the handlers that are being executed concurrently
+    /// (e.g., by [`crate::race`]) are dropped, and no other handler is
+    /// executed.
+    ///
+    /// Note that this only applies to the built-in handlers, such as
+    /// [`crate::filter`] or [`crate::endpoint`], and to containers that
+    /// implement [`DependencySupplier::try_get`] (e.g., [`DependencyMap`]).
+    /// The dispatches started by user-defined handlers on their own (e.g.,
+    /// with [`Handler::dispatch`]) are separate, so they panic as usual.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// use dptree::{prelude::*, DispatchError};
+    ///
+    /// let handler: Handler<_, _> = dptree::endpoint(|x: i32, s: String| async move { x });
+    ///
+    /// assert_eq!(
+    ///     handler.try_dispatch(dptree::deps![10, "abc".to_owned()]).await,
+    ///     Ok(ControlFlow::Break(10))
+    /// );
+    /// assert!(matches!(
+    ///     handler.try_dispatch(dptree::deps![10]).await,
+    ///     Err(DispatchError::MissingDependency(_))
+    /// ));
+    /// # }
+    /// ```
+    ///
+    /// [`DependencySupplier::try_get`]: crate::di::DependencySupplier::try_get
+    /// [`DependencyMap`]: crate::di::DependencyMap
+    pub async fn try_dispatch(
+        &self,
+        container: Input,
+    ) -> Result<ControlFlow<Output, Input>, DispatchError> {
+        let failure = Arc::new(Failure::default());
+        let ctx = Context::new(Env::failing_into(Arc::clone(&failure)));
+        let mut fut = Box::pin(self.dispatch_in(container, ctx));
+
+        poll_fn(|cx| {
+            let poll = fut.as_mut().poll(cx);
+
+            match failure.take() {
+                Some(error) => Poll::Ready(Err(error)),
+                None => poll.map(Ok),
+            }
+        })
+        .await
+    }
+
+    /// [`Handler::dispatch`] that also records a [`Trace`] of the dispatch.
+    ///
+    /// The trace contains every handler that has been entered and the
+    /// decision of every built-in handler (e.g., whether a filter has passed
+    /// the event further), along with the time spent in the injected
+    /// functions. This is useful to find out why an event was not handled.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// use dptree::{
+    ///     prelude::*,
+    ///     trace::{TraceEvent, Verdict},
+    /// };
+    ///
+    /// let handler: Handler<_, _> = dptree::filter(|x: i32| x > 0).endpoint(|| async { "done" });
+    ///
+    /// let (result, trace) = handler.dispatch_traced(dptree::deps![-10]).await;
+    /// assert_eq!(result, ControlFlow::Continue(dptree::deps![-10]));
+    ///
+    /// // Find out which handler has rejected the event.
+    /// let rejected_by = trace.events.iter().find_map(|event| match event {
+    ///     TraceEvent::Evaluated { node, verdict: Verdict::Reject, .. } => Some(node),
+    ///     _ => None,
+    /// });
+    /// println!("Rejected by {}", rejected_by.unwrap());
+    /// # }
+    /// ```
+    pub async fn dispatch_traced(&self, container: Input) -> (ControlFlow<Output, Input>, Trace) {
+        let trace = Arc::new(Mutex::new(Trace::default()));
+        let ctx = Context::new(Env::tracing_into(Arc::clone(&trace)));
+
+        let result = self.dispatch_in(container, ctx).await;
+        let trace = std::mem::take(&mut *trace.lock().unwrap_or_else(|error| error.into_inner()));
+        (result, trace)
     }
 
     /// Returns the set of updates that can be processed by this handler.
     pub fn description(&self) -> &Descr {
         &self.data.description
     }
+
+    pub(crate) fn op(&self) -> &Op<'a, Input, Output, Descr> {
+        &self.data.op
+    }
+
+    /// The program executing this handler, compiled on the first call.
+    pub(crate) fn program(&self) -> Program<'a, Input, Output, Descr> {
+        Arc::clone(self.data.program.get_or_init(|| compile::lower(self)))
+    }
 }
 
 /// Constructs a handler from a function.
@@ -225,6 +465,7 @@ where
 /// specialised functions: [`crate::endpoint`], [`crate::filter`],
 /// [`crate::filter_map`], etc.
 #[must_use]
+#[track_caller]
 pub fn from_fn<'a, F, Fut, Input, Output, Descr>(f: F) -> Handler<'a, Input, Output, Descr>
 where
     F: Fn(Input, Cont<'a, Input, Output>) -> Fut,
@@ -237,6 +478,7 @@ where
 
 /// [`from_fn`] with a custom description.
 #[must_use]
+#[track_caller]
 pub fn from_fn_with_description<'a, F, Fut, Input, Output, Descr>(
     description: Descr,
     f: F,
@@ -245,10 +487,124 @@ where
     F: Fn(Input, Cont<'a, Input, Output>) -> Fut,
     F: Send + Sync + 'a,
     Fut: Future<Output = ControlFlow<Output, Input>> + Send + 'a,
+{
+    from_fn_with_node(description, Some(NodeInfo::new(NodeKind::UserDefined)), f)
+}
+
+/// [`from_fn_with_description`] with custom run-time information about the
+/// handler.
+pub(crate) fn from_fn_with_node<'a, F, Fut, Input, Output, Descr>(
+    description: Descr,
+    node: Option<NodeInfo>,
+    f: F,
+) -> Handler<'a, Input, Output, Descr>
+where
+    F: Fn(Input, Cont<'a, Input, Output>) -> Fut,
+    F: Send + Sync + 'a,
+    Fut: Future<Output = ControlFlow<Output, Input>> + Send + 'a,
+{
+    from_fn_with_context(description, node, move |event, cont, _| f(event, cont))
+}
+
+/// [`from_fn_with_node`] for the handlers that execute other handlers, which
+/// receive the [context](Context) to execute those handlers in.
+pub(crate) fn from_fn_with_context<'a, F, Fut, Input, Output, Descr>(
+    description: Descr,
+    node: Option<NodeInfo>,
+    f: F,
+) -> Handler<'a, Input, Output, Descr>
+where
+    F: Fn(Input, Cont<'a, Input, Output>, Context<'a, Input, Output>) -> Fut,
+    F: Send + Sync + 'a,
+    Fut: Future<Output = ControlFlow<Output, Input>> + Send + 'a,
+{
+    from_fn_with_data(description, node, None, Op::Opaque, f)
+}
+
+/// Constructs a handler that combines other handlers as described by `op`.
+///
+/// Such a handler is executed by its [program](Handler::program), so that
+/// the stack depth does not depend on the depth of the combined handlers.
+pub(crate) fn from_op<'a, Input, Output, Descr>(
+    description: Descr,
+    op: Op<'a, Input, Output, Descr>,
+) -> Handler<'a, Input, Output, Descr>
+where
+    Input: 'a,
+    Output: 'a,
+{
+    from_fn_with_data(description, None, None, op, |_, _, _| async {
+        unreachable!("Combining handlers are executed by their programs")
+    })
+}
+
+/// Constructs a built-in handler that makes a decision with `step`, without
+/// taking the continuation.
+pub(crate) fn from_step<'a, F, Fut, Input, Output, Descr>(
+    description: Descr,
+    node: NodeInfo,
+    step: F,
+) -> Handler<'a, Input, Output, Descr>
+where
+    F: Fn(Input, Env) -> Fut,
+    F: Send + Sync + 'a,
+    Fut: Future<Output = Step<Output, Input>> + Send + 'a,
+    Input: Send + 'a,
+    Output: 'a,
+{
+    let step = Arc::new(step);
+    let op = Op::Step(node, {
+        let step = Arc::clone(&step);
+        Arc::new(move |event, env| Box::pin(step(event, env)) as BoxFuture<_>)
+    });
+
+    from_fn_with_data(description, Some(node), None, op, move |event, cont, ctx| {
+        let step = Arc::clone(&step);
+
+        async move {
+            // The step is not kept across the routing of an error, since
+            // `Output` may not be `Send`.
+            let passed = match step(event, ctx.env.clone()).await {
+                Step::Pass(event) => Ok((event, None)),
+                Step::Scoped { next, restore } => Ok((next, Some(restore))),
+                Step::Reject(event) | Step::Abort(event) => return ControlFlow::Continue(event),
+                Step::Break(output) => return ControlFlow::Break(output),
+                Step::Fail { event, failed, type_name } => Err((event, failed, type_name)),
+            };
+            let (next, restore) = match passed {
+                Ok(passed) => passed,
+                Err((event, failed, type_name)) => {
+                    return on_error::route(&ctx, event, failed, type_name).await
+                }
+            };
+
+            match cont(next).await {
+                ControlFlow::Continue(event) => ControlFlow::Continue(restore.unwrap_or(event)),
+                done => done,
+            }
+        }
+    })
+}
+
+fn from_fn_with_data<'a, F, Fut, Input, Output, Descr>(
+    description: Descr,
+    node: Option<NodeInfo>,
+    name: Option<&'static str>,
+    op: Op<'a, Input, Output, Descr>,
+    f: F,
+) -> Handler<'a, Input, Output, Descr>
+where
+    F: Fn(Input, Cont<'a, Input, Output>, Context<'a, Input, Output>) -> Fut,
+    F: Send + Sync + 'a,
+    Fut: Future<Output = ControlFlow<Output, Input>> + Send + 'a,
 {
     Handler {
         data: Arc::new(HandlerData {
-            f: move |event, cont| Box::pin(f(event, cont)) as HandlerResult<_, _>,
+            f: move |event, cont, ctx| Box::pin(f(event, cont, ctx)) as HandlerResult<_, _>,
+            node,
+            name,
+            op,
+            program: OnceLock::new(),
             description,
         }),
     }
@@ -266,7 +622,9 @@ where
     Output: 'a,
     Descr: HandlerDescription,
 {
-    from_fn_with_description(Descr::entry(), |event, cont| cont(event))
+    from_step(Descr::entry(), NodeInfo::new(NodeKind::Entry), |event, _| async move {
+        Step::Pass(event)
+    })
 }
 
 #[cfg(test)]
@@ -387,6 +745,78 @@ mod tests {
         assert_eq!(dispatcher.dispatch(deps![-2]).await, ControlFlow::Break(Output::LT));
     }
 
+    #[tokio::test]
+    async fn test_very_long_chain() {
+        const LENGTH: i32 = 100_000;
+
+        // Both dispatching and dropping the handler must not overflow the
+        // stack.
+        let handler = (0..LENGTH)
+            .fold(help_inference(entry()), |handler, _| {
+                handler.chain(filter(|| true)).chain(crate::map(|x: i32| x + 1))
+            })
+            .branch(filter(|x: i32| x < LENGTH).endpoint(|| async { unreachable!() }))
+            .endpoint(|x: i32| async move { x });
+
+        assert_eq!(handler.dispatch(deps![0]).await, ControlFlow::Break(LENGTH));
+        assert_eq!(handler.dispatch(deps![1]).await, ControlFlow::Break(LENGTH + 1));
+    }
+
+    #[tokio::test]
+    async fn test_try_dispatch() {
+        use crate::{di::MissingDependency, DispatchError};
+
+        let dispatcher = help_inference(entry())
+            .branch(filter(|x: i32| x == 1).endpoint(|| async { "one" }))
+            .branch(filter(|x: i32| x == 2).endpoint(|_: String| async { "two" }))
+            .branch(endpoint(|| async { unreachable!() }));
+
+        assert_eq!(dispatcher.try_dispatch(deps![1]).await, Ok(ControlFlow::Break("one")));
+        assert_eq!(
+            dispatcher.try_dispatch(deps![2]).await,
+            Err(DispatchError::MissingDependency(MissingDependency::new(
+                std::any::type_name::<String>(),
+                vec!["i32"]
+            )))
+        );
+
+        // The other arms of `race` are dropped.
+        let raced = help_inference(crate::race(vec![
+            endpoint(futures::future::pending::<&str>),
+            endpoint(|_: String| async { "two" }),
+        ]));
+        assert!(raced.try_dispatch(deps![1]).await.is_err());
+
+        // A dispatch started by a handler does not fail the outer one.
+        let nested = help_inference(endpoint(move || {
+            let dispatcher = dispatcher.clone();
+            async move { dispatcher.try_dispatch(deps![2]).await.is_err() }
+        }));
+        assert_eq!(nested.try_dispatch(deps![1]).await, Ok(ControlFlow::Break(true)));
+    }
+
+    #[tokio::test]
+    async fn test_named() {
+        async fn panic_message(handler: Handler<'static, DependencyMap, ()>) -> String {
+            let error = tokio::spawn(async move { handler.dispatch(deps![1]).await })
+                .await
+                .expect_err("The handler must panic");
+            *error.into_panic().downcast::<String>().unwrap()
+        }
+
+        let inner = help_inference(endpoint(|_: String| async {})).named("inner");
+        let message = panic_message(entry().branch(inner).named("outer")).await;
+        assert!(message.starts_with("In `outer > inner`: "), "{}", message);
+
+        // The continuation does not belong to the named subtree.
+        let handler = filter(|| true).named("filter").endpoint(|_: String| async {});
+        let message = panic_message(handler).await;
+        assert!(message.starts_with(std::any::type_name::<String>()), "{}", message);
+
+        assert!(format!("{:?}", entry::<(), (), description::Unspecified>().named("root"))
+            .contains("Some(\"root\")"));
+    }
+
     #[tokio::test]
     async fn allowed_updates() {
         use crate::description::{EventKind, InterestSet};
@@ -1,11 +1,19 @@
 //! Built-in handler description types.
 
+mod dependency_flow;
 mod interest_set;
+mod structure;
 mod unspecified;
 
-pub use interest_set::{EventKind, InterestSet};
+use std::fmt::{Display, Formatter};
+
+pub use dependency_flow::{Dependencies, DependencyFlow, UnsatisfiedDependencies};
+pub use interest_set::{Classify, EventKind, InterestSet};
+pub use structure::{Payload, Structure, StructureNode};
 pub use unspecified::Unspecified;
 
+use crate::di::TypeInfo;
+
 /// Handler description.
 ///
 /// This trait allows information to flow "back up" the tree, allowing to check
@@ -168,4 +176,117 @@ pub trait HandlerDescription: Sized + Send + Sync + 'static {
     fn endpoint() -> Self {
         Self::user_defined()
     }
+
+    /// Attaches the dependencies of a built-in handler to its description.
+    ///
+    /// This is called by the built-in handlers (such as [`filter`] or
+    /// [`map`]) right after the description has been constructed. `required`
+    /// are the types requested by the injected function, and `provided` are
+    /// the types that the handler inserts into the container.
+    ///
+    /// ## Default implementation
+    ///
+    /// By default this returns `self` unchanged.
+    ///
+    /// [`filter`]: crate::filter
+    /// [`map`]: crate::map
+    fn with_dependencies(self, required: Vec<TypeInfo>, provided: Vec<TypeInfo>) -> Self {
+        let _ = (required, provided);
+        self
+    }
+
+    /// Returns a description for a fallible handler (such as
+    /// [`map_result`]) that routes errors of the `raised` types to an error
+    /// handler.
+    ///
+    /// This is called by the fallible built-in handlers right after
+    /// [`with_dependencies`](HandlerDescription::with_dependencies).
+    ///
+    /// ## Default implementation
+    ///
+    /// By default this returns `self` unchanged.
+    ///
+    /// [`map_result`]: crate::map_result
+    fn with_errors(self, raised: Vec<TypeInfo>) -> Self {
+        let _ = raised;
+        self
+    }
+
+    /// Description for [`Handler::on_error`](crate::Handler::on_error), where
+    /// `self` describes the subtree and `handler` describes the error handler.
+    ///
+    /// ## Default implementation
+    ///
+    /// By default this returns the same as `self.merge_branch(handler)`.
+    fn on_error(&self, handler: &Self) -> Self {
+        self.merge_branch(handler)
+    }
+
+    /// Description for [`Handler::catch_unwind`](crate::Handler::catch_unwind),
+    /// where `self` describes the subtree and `fallback` describes the
+    /// handler executed on panics. `provided` are the types that are inserted
+    /// into the container of `fallback` (i.e.,
+    /// [`PanicPayload`](crate::PanicPayload)).
+    ///
+    /// ## Default implementation
+    ///
+    /// By default this returns the same as `self.merge_branch(fallback)`.
+    fn catch_unwind(&self, fallback: &Self, provided: Vec<TypeInfo>) -> Self {
+        let _ = provided;
+        self.merge_branch(fallback)
+    }
+
+    /// Description for [`Handler::named`](crate::Handler::named).
+    ///
+    /// ## Default implementation
+    ///
+    /// By default this returns the same as `Self::entry().merge_chain(self)`,
+    /// i.e., the name is ignored and the handler is described as if it was
+    /// chained to [`entry`](crate::entry).
+    #[track_caller]
+    fn named(&self, name: &'static str) -> Self {
+        let _ = name;
+        Self::entry().merge_chain(self)
+    }
+}
+
+/// A kind of a handler in a handler tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NodeKind {
+    /// [`entry`](crate::entry).
+    Entry,
+
+    /// A handler constructed from a user-defined function, e.g., via
+    /// [`from_fn`](crate::from_fn).
+    UserDefined,
+
+    /// [`filter`](crate::filter) and [`filter_async`](crate::filter_async).
+    Filter,
+
+    /// [`filter_map`](crate::filter_map) and
+    /// [`filter_map_async`](crate::filter_map_async).
+    FilterMap,
+
+    /// [`map`](crate::map) and [`map_async`](crate::map_async).
+    Map,
+
+    /// [`inspect`](crate::inspect) and [`inspect_async`](crate::inspect_async).
+    Inspect,
+
+    /// [`endpoint`](crate::endpoint).
+    Endpoint,
+}
+
+impl Display for NodeKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Entry => "entry",
+            Self::UserDefined => "user_defined",
+            Self::Filter => "filter",
+            Self::FilterMap => "filter_map",
+            Self::Map => "map",
+            Self::Inspect => "inspect",
+            Self::Endpoint => "endpoint",
+        })
+    }
 }
@@ -0,0 +1,267 @@
+use std::{
+    collections::HashSet,
+    fmt::{Display, Formatter},
+    panic::Location,
+};
+
+use crate::{
+    description::{NodeKind, Payload, Structure, StructureNode},
+    di::TypeInfo,
+    Handler,
+};
+
+/// Description for a handler that records how dependencies flow through a
+/// handler tree.
+///
+/// For each built-in handler, this description records the types requested by
+/// the injected function and the types inserted into the container (by
+/// [`filter_map`](crate::filter_map) and [`map`](crate::map)), along with the
+/// structure of the tree (this is a [`Structure`] whose handlers carry their
+/// [`Dependencies`]). This allows to check, before any event is dispatched,
+/// that every handler can obtain its dependencies: see
+/// [`Handler::verify_dependencies`].
+///
+/// Only containers that implement
+/// [`DependencySupplier::supplied_type`](crate::di::DependencySupplier::supplied_type)
+/// and [`Insert::inserted_type`](crate::di::Insert::inserted_type) (e.g.,
+/// [`DependencyMap`](crate::di::DependencyMap)) can be analysed. User-defined
+/// handlers are assumed to neither request nor provide anything. An error
+/// handler (see [`Handler::on_error`]) is assumed to receive the types
+/// available to its subtree, along with all errors that the subtree can
+/// raise. Likewise, a fallback of [`Handler::catch_unwind`] is assumed to
+/// receive the types available to its subtree, along with
+/// [`PanicPayload`](crate::PanicPayload).
+pub type DependencyFlow = Structure<Dependencies>;
+
+/// The dependencies of a single handler, recorded by [`DependencyFlow`].
+#[derive(Debug, Clone, Default)]
+pub struct Dependencies {
+    /// The types requested by the handler.
+    pub required: Vec<TypeInfo>,
+
+    /// The types inserted into the container by the handler.
+    pub provided: Vec<TypeInfo>,
+
+    /// The errors raised by the handler (see [`Handler::on_error`]).
+    pub raised: Vec<TypeInfo>,
+}
+
+impl Payload for Dependencies {
+    fn with_dependencies(&mut self, required: Vec<TypeInfo>, provided: Vec<TypeInfo>) {
+        self.required = required;
+        self.provided = provided;
+    }
+
+    fn with_errors(&mut self, raised: Vec<TypeInfo>) {
+        self.raised = raised;
+    }
+}
+
+/// A handler that would request a missing dependency at run-time.
+///
+/// See [`Handler::verify_dependencies`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsatisfiedDependencies {
+    /// The kind of the handler.
+    pub kind: NodeKind,
+
+    /// The location where the handler was constructed.
+    pub location: &'static Location<'static>,
+
+    /// The types that the handler requests, but that are not available.
+    pub missing: Vec<TypeInfo>,
+}
+
+impl Display for UnsatisfiedDependencies {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at {} requires types that are not provided:", self.kind, self.location)?;
+
+        for ty in &self.missing {
+            write!(f, " {}", ty)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for UnsatisfiedDependencies {}
+
+impl DependencyFlow {
+    /// Checks that the dependencies of every handler in the tree are
+    /// satisfied, given the `provided` types of an input container.
+    ///
+    /// Returns all handlers that would request missing dependencies.
+    pub fn verify(&self, provided: &[TypeInfo]) -> Vec<UnsatisfiedDependencies> {
+        let mut unsatisfied = Vec::new();
+        self.walk(provided.iter().copied().collect(), &mut unsatisfied);
+        unsatisfied
+    }
+
+    /// Returns the types available to the continuation of this handler.
+    fn walk(
+        &self,
+        mut available: HashSet<TypeInfo>,
+        unsatisfied: &mut Vec<UnsatisfiedDependencies>,
+    ) -> HashSet<TypeInfo> {
+        match self.node() {
+            StructureNode::Handler { kind, location, payload } => {
+                let missing: Vec<_> =
+                    payload.required.iter().filter(|ty| !available.contains(ty)).copied().collect();
+
+                if !missing.is_empty() {
+                    unsatisfied.push(UnsatisfiedDependencies { kind: *kind, location, missing });
+                }
+
+                available.extend(payload.provided.iter().copied());
+                available
+            }
+            StructureNode::Chain(first, second) => {
+                let available = first.walk(available, unsatisfied);
+                second.walk(available, unsatisfied)
+            }
+            StructureNode::Branch(first, second) => {
+                // Whatever the branch inserts is not visible to the rest of the chain.
+                let available = first.walk(available, unsatisfied);
+                second.walk(available.clone(), unsatisfied);
+                available
+            }
+            StructureNode::Named(_, handler) => handler.walk(available, unsatisfied),
+            StructureNode::OnError(handler, on_error) => {
+                let mut raised = Vec::new();
+                handler.raised(&mut raised);
+
+                let mut errors = available.clone();
+                errors.extend(raised);
+                on_error.walk(errors, unsatisfied);
+
+                handler.walk(available, unsatisfied)
+            }
+            StructureNode::CatchUnwind { handler, fallback, provided } => {
+                let mut payload = available.clone();
+                payload.extend(provided.iter().copied());
+                fallback.walk(payload, unsatisfied);
+
+                handler.walk(available, unsatisfied)
+            }
+        }
+    }
+
+    /// Collects the errors that propagate out of this handler.
+    fn raised(&self, out: &mut Vec<TypeInfo>) {
+        match self.node() {
+            StructureNode::Handler { payload, .. } => out.extend(payload.raised.iter().copied()),
+            StructureNode::Chain(first, second) | StructureNode::Branch(first, second) => {
+                first.raised(out);
+                second.raised(out);
+            }
+            StructureNode::Named(_, handler) => handler.raised(out),
+            // The errors of the subtree are handled by `on_error`.
+            StructureNode::OnError(_, on_error) => on_error.raised(out),
+            StructureNode::CatchUnwind { handler, fallback, .. } => {
+                handler.raised(out);
+                fallback.raised(out);
+            }
+        }
+    }
+}
+
+impl<'a, Input, Output> Handler<'a, Input, Output, DependencyFlow>
+where
+    Input: Send + 'a,
+    Output: 'a,
+{
+    /// Checks that every handler in this tree can obtain its dependencies
+    /// from a container with the `provided` types.
+    ///
+    /// This is a static analysis: no handler is executed. It reports every
+    /// handler that would cause [`DependencyMap`] to panic at run-time (or
+    /// [`Handler::try_dispatch`] to fail), which allows to catch configuration
+    /// errors at startup.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dptree::{description::DependencyFlow, di::TypeInfo, prelude::*};
+    ///
+    /// let handler: Handler<DependencyMap, (), DependencyFlow> = dptree::entry()
+    ///     .branch(dptree::filter_map(|x: i32| x.checked_add(1)).endpoint(|_: i32| async {}))
+    ///     .branch(dptree::endpoint(|_: String| async {}));
+    ///
+    /// assert!(handler.verify_dependencies(&dptree::deps![1, "a".to_owned()].types()).is_ok());
+    ///
+    /// let unsatisfied = handler.verify_dependencies(&[TypeInfo::of::<i32>()]).unwrap_err();
+    /// assert_eq!(unsatisfied.len(), 1);
+    /// assert_eq!(unsatisfied[0].missing, [TypeInfo::of::<String>()]);
+    /// ```
+    ///
+    /// [`DependencyMap`]: crate::di::DependencyMap
+    pub fn verify_dependencies(
+        &self,
+        provided: &[TypeInfo],
+    ) -> Result<(), Vec<UnsatisfiedDependencies>> {
+        let unsatisfied = self.description().verify(provided);
+
+        if unsatisfied.is_empty() {
+            Ok(())
+        } else {
+            Err(unsatisfied)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        deps,
+        description::{DependencyFlow, NodeKind},
+        di::{DependencyMap, TypeInfo},
+        endpoint, entry, filter, filter_map, map, map_result, Handler,
+    };
+
+    #[test]
+    fn verify_dependencies() {
+        #[derive(Clone)]
+        struct Db;
+
+        let handler: Handler<DependencyMap, (), DependencyFlow> = entry()
+            .branch(
+                filter(|x: i32| x > 0)
+                    .chain(map(|x: i32| x as u64))
+                    .branch(filter_map(|_: Db| Some("db")).endpoint(|_: &'static str| async {}))
+                    .endpoint(|_: u64, _: &'static str| async {}),
+            )
+            .branch(entry().endpoint(|_: u64| async {}));
+
+        assert!(handler.verify_dependencies(&deps![1i32, Db].types()).is_err());
+
+        let unsatisfied = handler.verify_dependencies(&deps![1i32].types()).unwrap_err();
+        let unsatisfied: Vec<_> = unsatisfied.into_iter().map(|u| (u.kind, u.missing)).collect();
+        assert_eq!(
+            unsatisfied,
+            [
+                (NodeKind::FilterMap, vec![TypeInfo::of::<Db>()]),
+                // `&str` is only provided to the branch above.
+                (NodeKind::Endpoint, vec![TypeInfo::of::<&str>()]),
+                // `u64` is only provided to the first branch.
+                (NodeKind::Endpoint, vec![TypeInfo::of::<u64>()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn verify_on_error() {
+        #[derive(Clone)]
+        struct Error;
+
+        let handler: Handler<DependencyMap, (), DependencyFlow> =
+            map_result(|x: i32| if x > 0 { Ok(x as u64) } else { Err(Error) })
+                .endpoint(|_: u64| async {})
+                .on_error(endpoint(|_: Error, _: i32| async {}));
+        assert!(handler.verify_dependencies(&deps![1i32].types()).is_ok());
+
+        // The error handler cannot obtain `u64`, which is inserted by the subtree.
+        let handler = handler.on_error(endpoint(|_: u64| async {}));
+        let unsatisfied = handler.verify_dependencies(&deps![1i32].types()).unwrap_err();
+        assert_eq!(unsatisfied[0].missing, [TypeInfo::of::<u64>()]);
+    }
+}
@@ -34,6 +34,14 @@ pub trait EventKind<S = RandomState>: Sized {
     fn empty_set() -> HashSet<Self, S>;
 }
 
+/// Determines the [`EventKind`] of an event.
+///
+/// See [`Handler::dispatch_classified`](crate::Handler::dispatch_classified).
+pub trait Classify<K> {
+    /// Returns the kind of this event.
+    fn classify(&self) -> K;
+}
+
 impl<K: EventKind<S>, S> InterestSet<K, S> {
     /// Constructs an [`InterestSet`] for a filter that allows to pass through
     /// it only updates with kinds in the `filtered` set.
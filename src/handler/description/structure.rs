@@ -0,0 +1,403 @@
+use std::{
+    fmt::{self, Debug, Write},
+    panic::Location,
+    sync::Arc,
+};
+
+use crate::{description::NodeKind, di::TypeInfo, Handler, HandlerDescription};
+
+/// Description for a handler that keeps the structure of a handler tree.
+///
+/// Each built-in handler is recorded along with its [kind](NodeKind), the
+/// location where it was constructed, and a [payload](Payload), and
+/// chains/branches are recorded as such. The resulting tree can be inspected
+/// with [`Structure::node`] or rendered with [`Handler::to_dot`].
+///
+/// The payload allows other descriptions to be built on top of this one: e.g.,
+/// [`DependencyFlow`](crate::description::DependencyFlow) is a structure whose
+/// handlers carry their dependencies.
+#[derive(Debug, Clone)]
+pub struct Structure<P = ()> {
+    node: Arc<StructureNode<P>>,
+}
+
+/// A node of [`Structure`].
+///
+/// There are no dedicated nodes for the other combinators, which are described
+/// in terms of these ones:
+///
+///  - [`crate::first_of`], [`crate::race`], [`crate::race_ordered`] and
+///    [`crate::broadcast`] are described as an [`NodeKind::Entry`] handler with
+///    the children branched from it, whether they are executed one by one or
+///    concurrently;
+///  - [`crate::switch`] and [`crate::router::Router`] are described as a
+///    [`NodeKind::FilterMap`] handler with every case or route branched from
+///    it, regardless of the key or path that selects it;
+///  - [`Handler::around`] is described as a [`NodeKind::UserDefined`] handler
+///    chained with the wrapped handler.
+#[derive(Debug, Clone)]
+pub enum StructureNode<P = ()> {
+    /// A single handler.
+    Handler {
+        /// The kind of the handler.
+        kind: NodeKind,
+
+        /// The location where the handler was constructed.
+        location: &'static Location<'static>,
+
+        /// The data attached to the handler.
+        payload: P,
+    },
+
+    /// Two handlers combined by [`Handler::chain`].
+    Chain(Structure<P>, Structure<P>),
+
+    /// Two handlers combined by [`Handler::branch`].
+    Branch(Structure<P>, Structure<P>),
+
+    /// A handler named with [`Handler::named`].
+    Named(&'static str, Structure<P>),
+
+    /// A handler and its error handler combined by [`Handler::on_error`].
+    OnError(Structure<P>, Structure<P>),
+
+    /// A handler and its fallback combined by [`Handler::catch_unwind`] (or
+    /// [`Handler::catch_unwind_with`], in which case the fallback is described
+    /// as an endpoint).
+    CatchUnwind {
+        /// The handler whose panics are caught.
+        handler: Structure<P>,
+
+        /// The handler executed on panics.
+        fallback: Structure<P>,
+
+        /// The types inserted into the container of `fallback`.
+        provided: Vec<TypeInfo>,
+    },
+}
+
+/// Data attached to each handler of a [`Structure`].
+///
+/// The methods of this trait are called with the corresponding methods of
+/// [`HandlerDescription`] on a single handler, and do nothing by default.
+pub trait Payload: Debug + Clone + Default + Send + Sync + 'static {
+    /// See [`HandlerDescription::with_dependencies`].
+    fn with_dependencies(&mut self, required: Vec<TypeInfo>, provided: Vec<TypeInfo>) {
+        let _ = (required, provided);
+    }
+
+    /// See [`HandlerDescription::with_errors`].
+    fn with_errors(&mut self, raised: Vec<TypeInfo>) {
+        let _ = raised;
+    }
+}
+
+impl Payload for () {}
+
+impl<P> Structure<P>
+where
+    P: Payload,
+{
+    #[track_caller]
+    fn handler(kind: NodeKind) -> Self {
+        let location = Location::caller();
+        Self::new(StructureNode::Handler { kind, location, payload: P::default() })
+    }
+
+    fn new(node: StructureNode<P>) -> Self {
+        Self { node: Arc::new(node) }
+    }
+
+    /// Modifies the payload of this handler, unless this is a combination of
+    /// handlers.
+    fn with_payload(mut self, f: impl FnOnce(&mut P)) -> Self {
+        if let StructureNode::Handler { payload, .. } = Arc::make_mut(&mut self.node) {
+            f(payload);
+        }
+
+        self
+    }
+
+    /// Returns the root node of this tree.
+    pub fn node(&self) -> &StructureNode<P> {
+        &self.node
+    }
+
+    /// Renders this tree as a [Graphviz] graph in the DOT language.
+    ///
+    /// Each handler is a graph node labelled with its kind and location. An
+    /// edge `a -> b` means that `b` can be executed right after `a` has passed
+    /// an event further; edges leading to branches are dashed. Named subtrees
+    /// are rendered as clusters. Error handlers and panic fallbacks are
+    /// connected to the entries of their subtrees by dotted edges.
+    ///
+    /// Since some combinators have no dedicated nodes (see [`StructureNode`]),
+    /// their graphs only approximate the execution: e.g., the children of
+    /// [`crate::race`] are rendered as branches, although they are executed
+    /// concurrently and only one of them is chosen.
+    ///
+    /// [Graphviz]: https://graphviz.org/
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph {\n    node [shape=box];\n");
+        let mut next_id = 0;
+        self.write_dot(&mut dot, &mut next_id).expect("Writing to a string cannot fail");
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Writes the nodes and edges of this subtree, returning the identifiers of
+    /// its entry nodes and of the nodes that pass events further.
+    fn write_dot(&self, dot: &mut String, next_id: &mut usize) -> Result<Ports, fmt::Error> {
+        match self.node() {
+            StructureNode::Handler { kind, location, .. } => {
+                let id = *next_id;
+                *next_id += 1;
+
+                writeln!(dot, "    n{} [label=\"{}\\n{}\"];", id, kind, escape(location))?;
+
+                // An endpoint never passes events further.
+                let exits = if *kind == NodeKind::Endpoint { vec![] } else { vec![id] };
+                Ok(Ports { entries: vec![id], exits })
+            }
+            StructureNode::Chain(first, second) => {
+                let first = first.write_dot(dot, next_id)?;
+                let second = second.write_dot(dot, next_id)?;
+                connect(dot, &first.exits, &second.entries, "")?;
+
+                Ok(Ports { entries: first.entries, exits: second.exits })
+            }
+            StructureNode::Branch(first, second) => {
+                let first = first.write_dot(dot, next_id)?;
+                let second = second.write_dot(dot, next_id)?;
+                connect(dot, &first.exits, &second.entries, " [style=dashed]")?;
+
+                // After a branch, the execution continues from `first`.
+                Ok(Ports { entries: first.entries, exits: first.exits })
+            }
+            StructureNode::OnError(handler, fallback)
+            | StructureNode::CatchUnwind { handler, fallback, .. } => {
+                let handler = handler.write_dot(dot, next_id)?;
+                let fallback = fallback.write_dot(dot, next_id)?;
+                connect(dot, &handler.entries, &fallback.entries, " [style=dotted]")?;
+
+                // A fallback never executes the continuation.
+                Ok(handler)
+            }
+            StructureNode::Named(name, handler) => {
+                let id = *next_id;
+                *next_id += 1;
+
+                writeln!(dot, "    subgraph cluster_{} {{", id)?;
+                writeln!(dot, "    label=\"{}\";", escape(name))?;
+                let ports = handler.write_dot(dot, next_id)?;
+                writeln!(dot, "    }}")?;
+
+                Ok(ports)
+            }
+        }
+    }
+}
+
+struct Ports {
+    entries: Vec<usize>,
+    exits: Vec<usize>,
+}
+
+fn escape(s: impl ToString) -> String {
+    s.to_string().replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn connect(dot: &mut String, from: &[usize], to: &[usize], attrs: &str) -> fmt::Result {
+    for from in from {
+        for to in to {
+            writeln!(dot, "    n{} -> n{}{};", from, to, attrs)?;
+        }
+    }
+
+    Ok(())
+}
+
+impl<P> HandlerDescription for Structure<P>
+where
+    P: Payload,
+{
+    #[track_caller]
+    fn entry() -> Self {
+        Self::handler(NodeKind::Entry)
+    }
+
+    #[track_caller]
+    fn user_defined() -> Self {
+        Self::handler(NodeKind::UserDefined)
+    }
+
+    fn merge_chain(&self, other: &Self) -> Self {
+        Self::new(StructureNode::Chain(self.clone(), other.clone()))
+    }
+
+    fn merge_branch(&self, other: &Self) -> Self {
+        Self::new(StructureNode::Branch(self.clone(), other.clone()))
+    }
+
+    #[track_caller]
+    fn map() -> Self {
+        Self::handler(NodeKind::Map)
+    }
+
+    #[track_caller]
+    fn map_async() -> Self {
+        Self::handler(NodeKind::Map)
+    }
+
+    #[track_caller]
+    fn filter() -> Self {
+        Self::handler(NodeKind::Filter)
+    }
+
+    #[track_caller]
+    fn filter_async() -> Self {
+        Self::handler(NodeKind::Filter)
+    }
+
+    #[track_caller]
+    fn filter_map() -> Self {
+        Self::handler(NodeKind::FilterMap)
+    }
+
+    #[track_caller]
+    fn filter_map_async() -> Self {
+        Self::handler(NodeKind::FilterMap)
+    }
+
+    #[track_caller]
+    fn inspect() -> Self {
+        Self::handler(NodeKind::Inspect)
+    }
+
+    #[track_caller]
+    fn inspect_async() -> Self {
+        Self::handler(NodeKind::Inspect)
+    }
+
+    #[track_caller]
+    fn endpoint() -> Self {
+        Self::handler(NodeKind::Endpoint)
+    }
+
+    fn with_dependencies(self, required: Vec<TypeInfo>, provided: Vec<TypeInfo>) -> Self {
+        self.with_payload(|payload| payload.with_dependencies(required, provided))
+    }
+
+    fn with_errors(self, raised: Vec<TypeInfo>) -> Self {
+        self.with_payload(|payload| payload.with_errors(raised))
+    }
+
+    fn on_error(&self, handler: &Self) -> Self {
+        Self::new(StructureNode::OnError(self.clone(), handler.clone()))
+    }
+
+    fn catch_unwind(&self, fallback: &Self, provided: Vec<TypeInfo>) -> Self {
+        Self::new(StructureNode::CatchUnwind {
+            handler: self.clone(),
+            fallback: fallback.clone(),
+            provided,
+        })
+    }
+
+    fn named(&self, name: &'static str) -> Self {
+        Self::new(StructureNode::Named(name, self.clone()))
+    }
+}
+
+impl<'a, Input, Output, P> Handler<'a, Input, Output, Structure<P>>
+where
+    Input: Send + 'a,
+    Output: 'a,
+    P: Payload,
+{
+    /// Renders this handler tree as a [Graphviz] graph in the DOT language.
+    ///
+    /// See [`Structure::to_dot`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dptree::{description::Structure, prelude::*};
+    ///
+    /// let handler: Handler<DependencyMap, (), Structure> = dptree::entry()
+    ///     .branch(dptree::filter(|x: i32| x > 0).endpoint(|| async {}))
+    ///     .branch(dptree::endpoint(|| async {}));
+    ///
+    /// // Render it with `dot -Tsvg tree.dot > tree.svg`.
+    /// let dot = handler.to_dot();
+    /// assert!(dot.starts_with("digraph {"));
+    /// ```
+    ///
+    /// [Graphviz]: https://graphviz.org/
+    pub fn to_dot(&self) -> String {
+        self.description().to_dot()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        description::{NodeKind, Structure, StructureNode},
+        di::DependencyMap,
+        entry, filter, Handler,
+    };
+
+    #[test]
+    fn to_dot() {
+        let handler: Handler<DependencyMap, (), Structure> = entry()
+            .branch(filter(|| true).endpoint(|| async {}))
+            .branch(filter(|| false).chain(filter(|| true)))
+            .endpoint(|| async {});
+
+        let dot = handler.to_dot();
+        let edges: Vec<_> = dot.lines().filter(|line| line.contains("->")).collect();
+
+        assert_eq!(
+            edges,
+            [
+                "    n1 -> n2;",
+                "    n0 -> n1 [style=dashed];",
+                "    n3 -> n4;",
+                "    n0 -> n3 [style=dashed];",
+                "    n0 -> n5;",
+            ]
+        );
+        assert!(dot.contains(&format!("n5 [label=\"endpoint\\n{}:", file!())));
+    }
+
+    #[test]
+    fn named() {
+        let handler: Handler<DependencyMap, (), Structure> =
+            entry().branch(filter(|| true).named("a \"quoted\" name")).endpoint(|| async {});
+
+        let dot = handler.to_dot();
+        assert!(
+            dot.contains("    subgraph cluster_1 {\n    label=\"a \\\"quoted\\\" name\";\n    n2 ")
+        );
+        assert!(dot.contains("    n0 -> n2 [style=dashed];\n"));
+    }
+
+    #[test]
+    fn node() {
+        let handler: Handler<DependencyMap, (), Structure> = filter(|| true).endpoint(|| async {});
+
+        match handler.description().node() {
+            StructureNode::Chain(first, second) => {
+                assert!(matches!(
+                    first.node(),
+                    StructureNode::Handler { kind: NodeKind::Filter, .. }
+                ));
+                assert!(matches!(
+                    second.node(),
+                    StructureNode::Handler { kind: NodeKind::Endpoint, .. }
+                ));
+            }
+            node => panic!("Unexpected node: {:?}", node),
+        }
+    }
+}
@@ -1,7 +1,7 @@
 use crate::HandlerDescription;
 
 /// Uninformative handler description.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Unspecified(());
 
 impl HandlerDescription for Unspecified {
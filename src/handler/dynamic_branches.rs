@@ -0,0 +1,206 @@
+use std::{
+    ops::ControlFlow,
+    sync::{Arc, Mutex},
+};
+
+use arc_swap::ArcSwap;
+
+use crate::{
+    description::{self, NodeKind},
+    handler::{core::from_fn_with_context, trace::NodeInfo},
+    Handler, HandlerDescription,
+};
+
+/// A set of branches that can be registered and unregistered at run time.
+///
+/// The handler constructed by [`DynamicBranches::handler`] executes the
+/// branches registered at the beginning of a dispatch one by one, in order of
+/// priority (the highest first; the branches with the same priority are
+/// executed in order of registration), until one breaks the execution; if none
+/// does, it continues the execution, just like [`crate::first_of`].
+///
+/// A dispatch takes a snapshot of the branches without locking, so registering
+/// and unregistering them neither blocks nor affects the dispatches in
+/// progress. Clones of `DynamicBranches` share the branches.
+///
+/// # Examples
+///
+/// ```
+/// use dptree::{prelude::*, DynamicBranches};
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let plugins: DynamicBranches<_, String, &str> = DynamicBranches::new();
+/// let handler: Handler<_, _> = plugins.handler().endpoint(|| async { "unhandled".to_owned() });
+///
+/// plugins.register("echo", 0, dptree::endpoint(|x: i32| async move { format!("{}", x) }));
+/// plugins.register(
+///     "negative",
+///     1,
+///     dptree::filter(|x: i32| x < 0).endpoint(|| async { "negative".to_owned() }),
+/// );
+/// assert_eq!(handler.dispatch(dptree::deps![-1]).await, ControlFlow::Break("negative".to_owned()));
+///
+/// plugins.unregister(&"negative");
+/// assert_eq!(handler.dispatch(dptree::deps![-1]).await, ControlFlow::Break("-1".to_owned()));
+/// # }
+/// ```
+pub struct DynamicBranches<'a, Input, Output, Id, Descr = description::Unspecified> {
+    branches: Arc<ArcSwap<Branches<'a, Input, Output, Id, Descr>>>,
+    /// Serializes the updates, so that none of them is lost.
+    updating: Arc<Mutex<()>>,
+}
+
+type Branches<'a, Input, Output, Id, Descr> = Vec<Branch<'a, Input, Output, Id, Descr>>;
+
+struct Branch<'a, Input, Output, Id, Descr> {
+    id: Id,
+    priority: i32,
+    handler: Handler<'a, Input, Output, Descr>,
+}
+
+impl<'a, Input, Output, Id, Descr> Clone for Branch<'a, Input, Output, Id, Descr>
+where
+    Id: Clone,
+{
+    fn clone(&self) -> Self {
+        Self { id: self.id.clone(), priority: self.priority, handler: self.handler.clone() }
+    }
+}
+
+impl<'a, Input, Output, Id, Descr> Clone for DynamicBranches<'a, Input, Output, Id, Descr> {
+    fn clone(&self) -> Self {
+        Self { branches: Arc::clone(&self.branches), updating: Arc::clone(&self.updating) }
+    }
+}
+
+impl<'a, Input, Output, Id, Descr> Default for DynamicBranches<'a, Input, Output, Id, Descr> {
+    fn default() -> Self {
+        Self { branches: Arc::new(ArcSwap::from_pointee(Vec::new())), updating: Arc::default() }
+    }
+}
+
+impl<'a, Input, Output, Id, Descr> DynamicBranches<'a, Input, Output, Id, Descr>
+where
+    Input: Send + 'a,
+    Output: 'a,
+    Id: PartialEq + Clone + Send + Sync + 'a,
+    Descr: HandlerDescription,
+{
+    /// Constructs an empty set of branches.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `handler` with `id` and `priority`, returning the handler
+    /// previously registered with `id`, if any.
+    pub fn register(
+        &self,
+        id: Id,
+        priority: i32,
+        handler: Handler<'a, Input, Output, Descr>,
+    ) -> Option<Handler<'a, Input, Output, Descr>> {
+        self.update(|branches| {
+            let previous = take(branches, &id);
+            let index = branches.partition_point(|branch| branch.priority >= priority);
+            branches.insert(index, Branch { id, priority, handler });
+            previous
+        })
+    }
+
+    /// Unregisters the handler registered with `id`, returning it, if any.
+    pub fn unregister(&self, id: &Id) -> Option<Handler<'a, Input, Output, Descr>> {
+        self.update(|branches| take(branches, id))
+    }
+
+    /// Returns the IDs of the registered handlers, in order of execution.
+    #[must_use]
+    pub fn ids(&self) -> Vec<Id> {
+        self.snapshot().iter().map(|branch| branch.id.clone()).collect()
+    }
+
+    /// Constructs a handler that executes the registered handlers.
+    ///
+    /// Since its description is fixed at this point, it is described as a
+    /// user-defined handler, whatever handlers are registered.
+    #[must_use]
+    #[track_caller]
+    pub fn handler(&self) -> Handler<'a, Input, Output, Descr> {
+        let this = self.clone();
+
+        from_fn_with_context(
+            Descr::user_defined(),
+            Some(NodeInfo::new(NodeKind::UserDefined)),
+            move |mut event, cont, ctx| {
+                let branches = this.snapshot();
+
+                async move {
+                    for branch in branches.iter() {
+                        match branch.handler.dispatch_in(event, ctx.clone()).await {
+                            ControlFlow::Continue(next) => event = next,
+                            done => return done,
+                        }
+                    }
+
+                    cont(event).await
+                }
+            },
+        )
+    }
+
+    fn snapshot(&self) -> Arc<Branches<'a, Input, Output, Id, Descr>> {
+        self.branches.load_full()
+    }
+
+    /// Replaces the branches with a modified copy, so that the snapshots taken
+    /// by the dispatches in progress stay intact.
+    fn update<T>(&self, f: impl FnOnce(&mut Branches<'a, Input, Output, Id, Descr>) -> T) -> T {
+        let _updating = self.updating.lock().unwrap_or_else(|error| error.into_inner());
+        let mut updated = Vec::clone(&self.branches.load());
+        let result = f(&mut updated);
+        self.branches.store(Arc::new(updated));
+        result
+    }
+}
+
+fn take<'a, Input, Output, Id, Descr>(
+    branches: &mut Vec<Branch<'a, Input, Output, Id, Descr>>,
+    id: &Id,
+) -> Option<Handler<'a, Input, Output, Descr>>
+where
+    Id: PartialEq,
+{
+    let index = branches.iter().position(|branch| branch.id == *id)?;
+    Some(branches.remove(index).handler)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{deps, di::DependencyMap, endpoint, filter};
+
+    #[tokio::test]
+    async fn dynamic_branches() {
+        let branches: DynamicBranches<DependencyMap, i32, &str> = DynamicBranches::new();
+        let handler = branches.handler().endpoint(|| async { 0 });
+
+        assert_eq!(handler.dispatch(deps![5]).await, ControlFlow::Break(0));
+
+        branches.register("a", 0, filter(|x: i32| x > 0).endpoint(|| async { 1 }));
+        branches.register("b", 1, filter(|x: i32| x > 1).endpoint(|| async { 2 }));
+        branches.register("c", 0, endpoint(|| async { 3 }));
+        assert_eq!(branches.ids(), ["b", "a", "c"]);
+        assert_eq!(handler.dispatch(deps![5]).await, ControlFlow::Break(2));
+        assert_eq!(handler.dispatch(deps![1]).await, ControlFlow::Break(1));
+        assert_eq!(handler.dispatch(deps![-1]).await, ControlFlow::Break(3));
+
+        // Registering with the same ID replaces the handler.
+        assert!(branches.register("a", 2, endpoint(|| async { 4 })).is_some());
+        assert_eq!(branches.ids(), ["a", "b", "c"]);
+        assert_eq!(handler.dispatch(deps![5]).await, ControlFlow::Break(4));
+
+        assert!(branches.unregister(&"a").is_some());
+        assert!(branches.unregister(&"a").is_none());
+        assert_eq!(handler.dispatch(deps![5]).await, ControlFlow::Break(2));
+    }
+}
@@ -1,6 +1,18 @@
-use crate::{description, di::Injectable, from_fn_with_description, Handler, HandlerDescription};
+use crate::{
+    description::{self, NodeKind},
+    di::{Injectable, Insert},
+    handler::{
+        compile::Step,
+        core::from_step,
+        error::inject,
+        on_error::raise,
+        spans,
+        trace::{self, NodeInfo, Verdict},
+    },
+    Handler, HandlerDescription,
+};
 use futures::FutureExt;
-use std::{ops::ControlFlow, sync::Arc};
+use std::sync::Arc;
 
 /// Constructs a handler that has no further handlers in a chain.
 ///
@@ -16,13 +28,69 @@ where
     Output: 'a,
     Descr: HandlerDescription,
 {
+    let description = Descr::endpoint()
+        .with_dependencies(<F as Injectable<Input, Output, FnArgs>>::input_types(), Vec::new());
+    let node = NodeInfo::new(NodeKind::Endpoint);
     let f = Arc::new(f);
 
-    from_fn_with_description(Descr::endpoint(), move |x, _cont| {
+    from_step(description, node, move |x, env| {
         let f = Arc::clone(&f);
         async move {
-            let f = f.inject(&x);
-            f().map(ControlFlow::Break).await
+            let Some(f) = inject(&*f, &x, &env) else {
+                return Step::Abort(x);
+            };
+            trace::evaluate(&env, node, spans::endpoint(node, f()), |_| Verdict::Break)
+                .map(Step::Break)
+                .await
+        }
+    })
+}
+
+/// Constructs an endpoint that can fail.
+///
+/// If `f` returns `Ok(output)`, then the execution is broken with `output`. If
+/// it returns `Err(e)`, then `e` will be added to a copy of the container and
+/// routed to the innermost error handler; if there is none, the dispatch
+/// fails. See [`Handler::on_error`].
+#[must_use]
+#[track_caller]
+pub fn endpoint_result<'a, F, Input, Output, E, FnArgs, Descr>(
+    f: F,
+) -> Endpoint<'a, Input, Output, Descr>
+where
+    F: Injectable<Input, Result<Output, E>, FnArgs> + Send + Sync + 'a,
+    Input: Insert<E> + Clone + Send + 'a,
+    Output: Send + 'a,
+    Descr: HandlerDescription,
+    E: Send,
+{
+    let description = Descr::endpoint()
+        .with_dependencies(
+            <F as Injectable<Input, Result<Output, E>, FnArgs>>::input_types(),
+            Vec::new(),
+        )
+        .with_errors(<Input as Insert<E>>::inserted_type().into_iter().collect());
+    let node = NodeInfo::new(NodeKind::Endpoint);
+    let f = Arc::new(f);
+
+    from_step(description, node, move |x, env| {
+        let f = Arc::clone(&f);
+        async move {
+            let res = {
+                let Some(f) = inject(&*f, &x, &env) else {
+                    return Step::Abort(x);
+                };
+                trace::evaluate(&env, node, spans::endpoint(node, f()), |res| match res {
+                    Ok(_) => Verdict::Break,
+                    Err(_) => Verdict::Fail,
+                })
+                .await
+            };
+
+            match res {
+                Ok(output) => Step::Break(output),
+                Err(error) => raise(x, error),
+            }
         }
     })
 }
@@ -33,6 +101,8 @@ pub type Endpoint<'a, Input, Output, Descr = description::Unspecified> =
 
 #[cfg(test)]
 mod tests {
+    use std::ops::ControlFlow;
+
     use super::*;
     use crate::{deps, help_inference};
 
@@ -54,4 +124,22 @@ mod tests {
         };
         assert_eq!(result, output);
     }
+
+    #[tokio::test]
+    async fn test_endpoint_result() {
+        let handler = help_inference(endpoint_result(|x: i32| async move {
+            if x > 0 {
+                Ok(x)
+            } else {
+                Err("non-positive")
+            }
+        }))
+        .on_error(crate::endpoint(|e: &'static str, x: i32| async move {
+            assert_eq!(e, "non-positive");
+            x.abs()
+        }));
+
+        assert_eq!(handler.dispatch(deps![1]).await, ControlFlow::Break(1));
+        assert_eq!(handler.dispatch(deps![-2]).await, ControlFlow::Break(2));
+    }
 }
@@ -0,0 +1,95 @@
+use std::{
+    fmt::{Display, Formatter},
+    sync::Mutex,
+};
+
+use crate::{
+    di::{CompiledFn, Injectable, MissingDependency},
+    handler::context::Env,
+};
+
+/// An error that can occur during [`crate::Handler::try_dispatch`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DispatchError {
+    /// A handler requested a dependency that was not provided.
+    MissingDependency(MissingDependency),
+
+    /// A fallible handler (such as [`crate::map_result`]) returned an error,
+    /// but there was no [`crate::Handler::on_error`] to handle it.
+    UnhandledError {
+        /// The type of the error.
+        type_name: &'static str,
+    },
+}
+
+impl Display for DispatchError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingDependency(error) => Display::fmt(error, f),
+            Self::UnhandledError { type_name } => {
+                write!(f, "{} was returned as an error, but there is no error handler", type_name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DispatchError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::MissingDependency(error) => Some(error),
+            Self::UnhandledError { .. } => None,
+        }
+    }
+}
+
+impl From<MissingDependency> for DispatchError {
+    fn from(error: MissingDependency) -> Self {
+        Self::MissingDependency(error)
+    }
+}
+
+/// The error of a [`crate::Handler::try_dispatch`] call.
+#[derive(Debug, Default)]
+pub(crate) struct Failure(Mutex<Option<DispatchError>>);
+
+impl Failure {
+    /// Records `error`, unless another one has been recorded already.
+    pub(crate) fn set(&self, error: DispatchError) {
+        // Concurrently running handlers may fail as well; keep the first error.
+        self.lock().get_or_insert(error);
+    }
+
+    pub(crate) fn is_set(&self) -> bool {
+        self.lock().is_some()
+    }
+
+    pub(crate) fn take(&self) -> Option<DispatchError> {
+        self.lock().take()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<DispatchError>> {
+        self.0.lock().unwrap_or_else(|error| error.into_inner())
+    }
+}
+
+/// Injects dependencies into `f`.
+///
+/// If some dependency is missing, the dispatch executed in `env` fails (see
+/// [`Env::fail`]), and `None` is returned.
+pub(crate) fn inject<'a, F, Input, Output, FnArgs>(
+    f: &'a F,
+    container: &'a Input,
+    env: &Env,
+) -> Option<CompiledFn<'a, Output>>
+where
+    F: Injectable<Input, Output, FnArgs>,
+    FnArgs: 'a,
+{
+    match f.try_inject(container) {
+        Ok(f) => Some(f),
+        Err(error) => {
+            env.fail(DispatchError::MissingDependency(error));
+            None
+        }
+    }
+}
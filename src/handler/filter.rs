@@ -1,16 +1,21 @@
 use crate::{
+    description::NodeKind,
     di::{Asyncify, Injectable},
-    from_fn_with_description,
-    handler::core::Handler,
+    handler::{
+        compile::Step,
+        core::{from_step, Handler},
+        error::inject,
+        trace::{self, NodeInfo, Verdict},
+    },
     HandlerDescription,
 };
-use std::{ops::ControlFlow, sync::Arc};
+use std::sync::Arc;
 
 /// Constructs a handler that filters input with the predicate `pred`.
 ///
 /// `pred` has an access to all values that are stored in the input container.
 /// If it returns `true`, a continuation of the handler will be called,
-/// otherwise the handler returns [`ControlFlow::Continue`].
+/// otherwise the handler returns [`ControlFlow::Continue`](std::ops::ControlFlow::Continue).
 #[must_use]
 #[track_caller]
 pub fn filter<'a, Pred, Input, Output, FnArgs, Descr>(
@@ -22,7 +27,7 @@ where
     Output: 'a,
     Descr: HandlerDescription,
 {
-    filter_with_description(Descr::filter(), pred)
+    filter_with_dependencies(Descr::filter(), Asyncify(pred))
 }
 
 /// The asynchronous version of [`filter`].
@@ -37,11 +42,15 @@ where
     Output: 'a,
     Descr: HandlerDescription,
 {
-    filter_async_with_description(Descr::filter_async(), pred)
+    filter_with_dependencies(Descr::filter_async(), pred)
 }
 
 /// [`filter`] with a custom description.
+///
+/// Unlike [`filter`], this does not add the dependencies of `pred` to
+/// `description` (see [`HandlerDescription::with_dependencies`]).
 #[must_use]
+#[track_caller]
 pub fn filter_with_description<'a, Pred, Input, Output, FnArgs, Descr>(
     description: Descr,
     pred: Pred,
@@ -54,8 +63,28 @@ where
     filter_async_with_description(description, Asyncify(pred))
 }
 
+#[track_caller]
+fn filter_with_dependencies<'a, Pred, Input, Output, FnArgs, Descr>(
+    description: Descr,
+    pred: Pred,
+) -> Handler<'a, Input, Output, Descr>
+where
+    Pred: Injectable<Input, bool, FnArgs> + Send + Sync + 'a,
+    Input: Send + 'a,
+    Output: 'a,
+    Descr: HandlerDescription,
+{
+    let description = description
+        .with_dependencies(<Pred as Injectable<Input, bool, FnArgs>>::input_types(), Vec::new());
+    filter_async_with_description(description, pred)
+}
+
 /// [`filter_async`] with a custom description.
+///
+/// Unlike [`filter_async`], this does not add the dependencies of `pred` to
+/// `description` (see [`HandlerDescription::with_dependencies`]).
 #[must_use]
+#[track_caller]
 pub fn filter_async_with_description<'a, Pred, Input, Output, FnArgs, Descr>(
     description: Descr,
     pred: Pred,
@@ -65,27 +94,40 @@ where
     Input: Send + 'a,
     Output: 'a,
 {
+    let node = NodeInfo::new(NodeKind::Filter);
     let pred = Arc::new(pred);
 
-    from_fn_with_description(description, move |event, cont| {
+    from_step(description, node, move |event, env| {
         let pred = Arc::clone(&pred);
 
         async move {
-            let pred = pred.inject(&event);
-            let cond = pred().await;
+            let Some(pred) = inject(&*pred, &event, &env) else {
+                return Step::Abort(event);
+            };
+            let cond = trace::evaluate(&env, node, pred(), |&cond| verdict(cond)).await;
             drop(pred);
 
             if cond {
-                cont(event).await
+                Step::Pass(event)
             } else {
-                ControlFlow::Continue(event)
+                Step::Reject(event)
             }
         }
     })
 }
 
+fn verdict(cond: bool) -> Verdict {
+    if cond {
+        Verdict::Pass
+    } else {
+        Verdict::Reject
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use std::ops::ControlFlow;
+
     use super::*;
     use crate::{deps, help_inference};
 
@@ -1,14 +1,22 @@
 use crate::{
+    description::NodeKind,
     di::{Asyncify, Injectable, Insert},
-    from_fn_with_description, Handler, HandlerDescription,
+    handler::{
+        compile::Step,
+        core::from_step,
+        error::inject,
+        on_error::raise,
+        trace::{self, NodeInfo, Verdict},
+    },
+    Handler, HandlerDescription,
 };
-use std::{ops::ControlFlow, sync::Arc};
+use std::sync::Arc;
 
 /// Constructs a handler that optionally passes a value of a new type further.
 ///
 /// If the `proj` function returns `Some(v)` then `v` will be added to the
 /// container and passed further in a handler chain. If the function returns
-/// `None`, then the handler will return [`ControlFlow::Continue`] with the old
+/// `None`, then the handler will return [`ControlFlow::Continue`](std::ops::ControlFlow::Continue) with the old
 /// container.
 #[must_use]
 #[track_caller]
@@ -23,7 +31,7 @@ where
     Descr: HandlerDescription,
     NewType: Send,
 {
-    filter_map_with_description(Descr::filter_map(), proj)
+    filter_map_with_dependencies(Descr::filter_map(), Asyncify(proj))
 }
 
 /// The asynchronous version of [`filter_map`].
@@ -40,11 +48,15 @@ where
     Descr: HandlerDescription,
     NewType: Send,
 {
-    filter_map_async_with_description(Descr::filter_map_async(), proj)
+    filter_map_with_dependencies(Descr::filter_map_async(), proj)
 }
 
 /// [`filter_map`] with a custom description.
+///
+/// Unlike [`filter_map`], this does not add the dependencies of `proj` to
+/// `description` (see [`HandlerDescription::with_dependencies`]).
 #[must_use]
+#[track_caller]
 pub fn filter_map_with_description<'a, Projection, Input, Output, NewType, Args, Descr>(
     description: Descr,
     proj: Projection,
@@ -59,8 +71,32 @@ where
     filter_map_async_with_description(description, Asyncify(proj))
 }
 
+#[track_caller]
+fn filter_map_with_dependencies<'a, Projection, Input, Output, NewType, Args, Descr>(
+    description: Descr,
+    proj: Projection,
+) -> Handler<'a, Input, Output, Descr>
+where
+    Input: Clone,
+    Projection: Injectable<Input, Option<NewType>, Args> + Send + Sync + 'a,
+    Input: Insert<NewType> + Send + 'a,
+    Output: 'a,
+    Descr: HandlerDescription,
+    NewType: Send,
+{
+    let description = description.with_dependencies(
+        <Projection as Injectable<Input, Option<NewType>, Args>>::input_types(),
+        <Input as Insert<NewType>>::inserted_type().into_iter().collect(),
+    );
+    filter_map_async_with_description(description, proj)
+}
+
 /// [`filter_map_async`] with a custom description.
+///
+/// Unlike [`filter_map_async`], this does not add the dependencies of `proj`
+/// to `description` (see [`HandlerDescription::with_dependencies`]).
 #[must_use]
+#[track_caller]
 pub fn filter_map_async_with_description<'a, Projection, Input, Output, NewType, Args, Descr>(
     description: Descr,
     proj: Projection,
@@ -72,26 +108,155 @@ where
     Output: 'a,
     NewType: Send,
 {
+    let node = NodeInfo::new(NodeKind::FilterMap);
     let proj = Arc::new(proj);
 
-    from_fn_with_description(description, move |container: Input, cont| {
+    from_step(description, node, move |container: Input, env| {
         let proj = Arc::clone(&proj);
 
         async move {
-            let proj = proj.inject(&container);
-            let res = proj().await;
+            let Some(proj) = inject(&*proj, &container, &env) else {
+                return Step::Abort(container);
+            };
+            let res = trace::evaluate(&env, node, proj(), |res| match res {
+                Some(_) => Verdict::Pass,
+                None => Verdict::Reject,
+            })
+            .await;
             std::mem::drop(proj);
 
             match res {
                 Some(new_type) => {
-                    let mut intermediate = container.clone();
-                    intermediate.insert(new_type);
-                    match cont(intermediate).await {
-                        ControlFlow::Continue(_) => ControlFlow::Continue(container),
-                        ControlFlow::Break(result) => ControlFlow::Break(result),
-                    }
+                    let mut next = container.clone();
+                    next.insert(new_type);
+                    Step::Scoped { next, restore: container }
+                }
+                None => Step::Reject(container),
+            }
+        }
+    })
+}
+
+/// Constructs a handler that optionally passes a value of a new type further,
+/// or fails.
+///
+/// If `proj` returns `Ok(Some(v))`, then `v` will be added to the container
+/// and passed further in a handler chain. If it returns `Ok(None)`, then the
+/// handler will return [`ControlFlow::Continue`](std::ops::ControlFlow::Continue) with the old container. If it
+/// returns `Err(e)`, then `e` will be added to a copy of the container and
+/// routed to the innermost error handler; if there is none, the dispatch
+/// fails. See [`Handler::on_error`].
+#[must_use]
+#[track_caller]
+pub fn filter_map_result<'a, Projection, Input, Output, NewType, E, Args, Descr>(
+    proj: Projection,
+) -> Handler<'a, Input, Output, Descr>
+where
+    Input: Clone,
+    Asyncify<Projection>: Injectable<Input, Result<Option<NewType>, E>, Args> + Send + Sync + 'a,
+    Input: Insert<NewType> + Insert<E> + Send + 'a,
+    Output: 'a,
+    Descr: HandlerDescription,
+    NewType: Send,
+    E: Send,
+{
+    filter_map_result_with_description(Descr::filter_map(), proj)
+}
+
+/// The asynchronous version of [`filter_map_result`].
+#[must_use]
+#[track_caller]
+pub fn filter_map_result_async<'a, Projection, Input, Output, NewType, E, Args, Descr>(
+    proj: Projection,
+) -> Handler<'a, Input, Output, Descr>
+where
+    Input: Clone,
+    Projection: Injectable<Input, Result<Option<NewType>, E>, Args> + Send + Sync + 'a,
+    Input: Insert<NewType> + Insert<E> + Send + 'a,
+    Output: 'a,
+    Descr: HandlerDescription,
+    NewType: Send,
+    E: Send,
+{
+    filter_map_result_async_with_description(Descr::filter_map_async(), proj)
+}
+
+/// [`filter_map_result`] with a custom description.
+#[must_use]
+#[track_caller]
+pub fn filter_map_result_with_description<'a, Projection, Input, Output, NewType, E, Args, Descr>(
+    description: Descr,
+    proj: Projection,
+) -> Handler<'a, Input, Output, Descr>
+where
+    Input: Clone,
+    Asyncify<Projection>: Injectable<Input, Result<Option<NewType>, E>, Args> + Send + Sync + 'a,
+    Input: Insert<NewType> + Insert<E> + Send + 'a,
+    Output: 'a,
+    Descr: HandlerDescription,
+    NewType: Send,
+    E: Send,
+{
+    filter_map_result_async_with_description(description, Asyncify(proj))
+}
+
+/// [`filter_map_result_async`] with a custom description.
+#[must_use]
+#[track_caller]
+pub fn filter_map_result_async_with_description<
+    'a,
+    Projection,
+    Input,
+    Output,
+    NewType,
+    E,
+    Args,
+    Descr,
+>(
+    description: Descr,
+    proj: Projection,
+) -> Handler<'a, Input, Output, Descr>
+where
+    Input: Clone,
+    Projection: Injectable<Input, Result<Option<NewType>, E>, Args> + Send + Sync + 'a,
+    Input: Insert<NewType> + Insert<E> + Send + 'a,
+    Output: 'a,
+    Descr: HandlerDescription,
+    NewType: Send,
+    E: Send,
+{
+    let description = description
+        .with_dependencies(
+            <Projection as Injectable<Input, Result<Option<NewType>, E>, Args>>::input_types(),
+            <Input as Insert<NewType>>::inserted_type().into_iter().collect(),
+        )
+        .with_errors(<Input as Insert<E>>::inserted_type().into_iter().collect());
+    let node = NodeInfo::new(NodeKind::FilterMap);
+    let proj = Arc::new(proj);
+
+    from_step(description, node, move |container: Input, env| {
+        let proj = Arc::clone(&proj);
+
+        async move {
+            let Some(proj) = inject(&*proj, &container, &env) else {
+                return Step::Abort(container);
+            };
+            let res = trace::evaluate(&env, node, proj(), |res| match res {
+                Ok(Some(_)) => Verdict::Pass,
+                Ok(None) => Verdict::Reject,
+                Err(_) => Verdict::Fail,
+            })
+            .await;
+            std::mem::drop(proj);
+
+            match res {
+                Ok(Some(new_type)) => {
+                    let mut next = container.clone();
+                    next.insert(new_type);
+                    Step::Scoped { next, restore: container }
                 }
-                None => ControlFlow::Continue(container),
+                Ok(None) => Step::Reject(container),
+                Err(error) => raise(container, error),
             }
         }
     })
@@ -99,6 +264,8 @@ where
 
 #[cfg(test)]
 mod tests {
+    use std::ops::ControlFlow;
+
     use super::*;
     use crate::{deps, help_inference};
 
@@ -126,4 +293,19 @@ mod tests {
 
         assert!(result == ControlFlow::Continue(crate::deps![]));
     }
+
+    #[tokio::test]
+    async fn test_result() {
+        let handler = help_inference(filter_map_result(|x: i32| match x {
+            0 => Err("zero"),
+            x if x > 0 => Ok(Some(x as u64)),
+            _ => Ok(None),
+        }))
+        .endpoint(|x: u64| async move { x.to_string() })
+        .on_error(crate::endpoint(|e: &'static str| async move { e.to_owned() }));
+
+        assert_eq!(handler.dispatch(deps![1]).await, ControlFlow::Break("1".to_owned()));
+        assert_eq!(handler.dispatch(deps![0]).await, ControlFlow::Break("zero".to_owned()));
+        assert_eq!(handler.dispatch(deps![-1]).await, ControlFlow::Continue(deps![-1]));
+    }
 }
@@ -0,0 +1,144 @@
+use crate::{
+    handler::{compile::Op, core::from_op},
+    Handler, HandlerDescription,
+};
+
+/// Constructs a handler that executes the handlers of `children` one by one,
+/// until some of them breaks the execution.
+///
+/// This is the same as `dptree::entry().branch(a).branch(b)...`, but the
+/// children are stored in a single handler, which iterates over them. Thus,
+/// the nesting of handlers (and the stack depth during a dispatch) does not
+/// grow with the number of children, which matters for large dispatchers.
+///
+/// The description is merged as if the children were branched from
+/// [`crate::entry`].
+///
+/// See also: [`Handler::branch_many`].
+///
+/// # Examples
+///
+/// ```
+/// use dptree::prelude::*;
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let commands: Vec<Handler<_, _>> = (0..100)
+///     .map(|i| {
+///         dptree::filter(move |cmd: u32| cmd == i)
+///             .endpoint(move || async move { format!("command #{}", i) })
+///     })
+///     .collect();
+/// let handler = dptree::first_of(commands);
+///
+/// assert_eq!(handler.dispatch(dptree::deps![42u32]).await, ControlFlow::Break("command #42".to_owned()));
+/// assert_eq!(handler.dispatch(dptree::deps![100u32]).await, ControlFlow::Continue(dptree::deps![100u32]));
+/// # }
+/// ```
+#[must_use]
+#[track_caller]
+pub fn first_of<'a, Input, Output, Descr, I>(children: I) -> Handler<'a, Input, Output, Descr>
+where
+    I: IntoIterator<Item = Handler<'a, Input, Output, Descr>>,
+    Input: Send + 'a,
+    Output: Send + 'a,
+    Descr: HandlerDescription,
+{
+    let children: Vec<_> = children.into_iter().collect();
+    let description = merge_branches(Descr::entry(), &children);
+
+    from_op(description, Op::Branch(None, children))
+}
+
+impl<'a, Input, Output, Descr> Handler<'a, Input, Output, Descr>
+where
+    Input: Send + 'a,
+    Output: Send + 'a,
+    Descr: HandlerDescription,
+{
+    /// Branches all handlers of `children` from this one.
+    ///
+    /// This is the same as `self.branch(a).branch(b)...`, but the children
+    /// are executed by a single handler. See [`crate::first_of`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dptree::prelude::*;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let handler: Handler<_, _> = dptree::filter(|x: i32| x > 0).branch_many(vec![
+    ///     dptree::filter(|x: i32| x % 2 == 0).endpoint(|| async { "even" }),
+    ///     dptree::endpoint(|| async { "odd" }),
+    /// ]);
+    ///
+    /// assert_eq!(handler.dispatch(dptree::deps![2]).await, ControlFlow::Break("even"));
+    /// assert_eq!(handler.dispatch(dptree::deps![3]).await, ControlFlow::Break("odd"));
+    /// assert_eq!(handler.dispatch(dptree::deps![-1]).await, ControlFlow::Continue(dptree::deps![-1]));
+    /// # }
+    /// ```
+    #[must_use]
+    #[track_caller]
+    pub fn branch_many<I>(self, children: I) -> Self
+    where
+        I: IntoIterator<Item = Self>,
+    {
+        let children: Vec<_> = children.into_iter().collect();
+        let description = match children.split_first() {
+            Some((first, rest)) => {
+                merge_branches(self.description().merge_branch(first.description()), rest)
+            }
+            None => Descr::entry().merge_chain(self.description()),
+        };
+
+        from_op(description, Op::Branch(Some(self), children))
+    }
+}
+
+fn merge_branches<'a, Input, Output, Descr>(
+    description: Descr,
+    children: &[Handler<'a, Input, Output, Descr>],
+) -> Descr
+where
+    Input: Send + 'a,
+    Output: 'a,
+    Descr: HandlerDescription,
+{
+    children
+        .iter()
+        .fold(description, |description, child| description.merge_branch(child.description()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::ControlFlow;
+
+    use super::*;
+    use crate::{deps, di::DependencyMap, endpoint, filter};
+
+    #[tokio::test]
+    async fn first_of() {
+        let children = || -> Vec<Handler<DependencyMap, i32>> {
+            vec![
+                filter(|x: i32| x == 1).endpoint(|| async { 10 }),
+                filter(|x: i32| x == 2).endpoint(|| async { 20 }),
+                filter(|x: i32| x > 2).endpoint(|x: i32| async move { x }),
+            ]
+        };
+
+        let handler = super::first_of(children()).branch(endpoint(|| async { 0 }));
+        for (input, output) in [(1, 10), (2, 20), (3, 3), (0, 0)].iter() {
+            assert_eq!(handler.dispatch(deps![*input]).await, ControlFlow::Break(*output));
+        }
+
+        let handler = filter(|x: i32| x != 1).branch_many(children());
+        assert_eq!(handler.dispatch(deps![1]).await, ControlFlow::Continue(deps![1]));
+        assert_eq!(handler.dispatch(deps![2]).await, ControlFlow::Break(20));
+        assert_eq!(handler.dispatch(deps![0]).await, ControlFlow::Continue(deps![0]));
+
+        let deep =
+            super::first_of((0..10_000).flat_map(|_| children())).branch(endpoint(|| async { 0 }));
+        assert_eq!(deep.dispatch(deps![0]).await, ControlFlow::Break(0));
+    }
+}
@@ -1,6 +1,13 @@
 use crate::{
+    description::NodeKind,
     di::{Asyncify, Injectable},
-    from_fn_with_description, Handler, HandlerDescription,
+    handler::{
+        compile::Step,
+        core::from_step,
+        error::inject,
+        trace::{self, NodeInfo, Verdict},
+    },
+    Handler, HandlerDescription,
 };
 
 use std::sync::Arc;
@@ -18,7 +25,7 @@ where
     Output: 'a,
     Descr: HandlerDescription,
 {
-    inspect_with_description(Descr::inspect(), f)
+    inspect_with_dependencies(Descr::inspect(), Asyncify(f))
 }
 
 /// The asynchronous version of [`inspect`].
@@ -31,11 +38,15 @@ where
     Output: 'a,
     Descr: HandlerDescription,
 {
-    inspect_async_with_description(Descr::inspect_async(), f)
+    inspect_with_dependencies(Descr::inspect_async(), f)
 }
 
 /// [`inspect`] with a custom description.
+///
+/// Unlike [`inspect`], this does not add the dependencies of `f` to
+/// `description` (see [`HandlerDescription::with_dependencies`]).
 #[must_use]
+#[track_caller]
 pub fn inspect_with_description<'a, F, Input, Output, Args, Descr>(
     description: Descr,
     f: F,
@@ -48,8 +59,28 @@ where
     inspect_async_with_description(description, Asyncify(f))
 }
 
+#[track_caller]
+fn inspect_with_dependencies<'a, F, Input, Output, Args, Descr>(
+    description: Descr,
+    f: F,
+) -> Handler<'a, Input, Output, Descr>
+where
+    F: Injectable<Input, (), Args> + Send + Sync + 'a,
+    Input: Send + 'a,
+    Output: 'a,
+    Descr: HandlerDescription,
+{
+    let description = description
+        .with_dependencies(<F as Injectable<Input, (), Args>>::input_types(), Vec::new());
+    inspect_async_with_description(description, f)
+}
+
 /// [`inspect_async`] with a custom description.
+///
+/// Unlike [`inspect_async`], this does not add the dependencies of `f` to
+/// `description` (see [`HandlerDescription::with_dependencies`]).
 #[must_use]
+#[track_caller]
 pub fn inspect_async_with_description<'a, F, Input, Output, Args, Descr>(
     description: Descr,
     f: F,
@@ -59,17 +90,20 @@ where
     Input: Send + 'a,
     Output: 'a,
 {
+    let node = NodeInfo::new(NodeKind::Inspect);
     let f = Arc::new(f);
 
-    from_fn_with_description(description, move |x, cont| {
+    from_step(description, node, move |x, env| {
         let f = Arc::clone(&f);
         async move {
             {
-                let f = f.inject(&x);
-                f().await;
+                let Some(f) = inject(&*f, &x, &env) else {
+                    return Step::Abort(x);
+                };
+                trace::evaluate(&env, node, f(), |_| Verdict::Pass).await;
             }
 
-            cont(x).await
+            Step::Pass(x)
         }
     })
 }
@@ -0,0 +1,222 @@
+//! Skipping the subtrees that are not interested in the kind of the event
+//! being dispatched.
+//!
+//! See [`crate::Handler::dispatch_with_kind`].
+
+use std::{
+    any::Any,
+    hash::{BuildHasher, Hash},
+    ops::ControlFlow,
+    sync::Arc,
+};
+
+use crate::{
+    description::{Classify, EventKind, InterestSet},
+    handler::context::{Context, Env},
+    Handler,
+};
+
+/// Decides whether a subtree with the given description can be skipped.
+struct Predicate<Descr>(Box<dyn Fn(&Descr) -> bool + Send + Sync>);
+
+/// The predicate of a dispatch with a known event kind, if any.
+#[derive(Clone, Default)]
+pub(crate) struct Skip(Option<Arc<dyn Any + Send + Sync>>);
+
+impl Skip {
+    fn new<Descr, F>(predicate: F) -> Self
+    where
+        Descr: 'static,
+        F: Fn(&Descr) -> bool + Send + Sync + 'static,
+    {
+        Self(Some(Arc::new(Predicate(Box::new(predicate)))))
+    }
+
+    /// Whether a subtree described by `description` can be skipped.
+    ///
+    /// Descriptions of other types are never skipped.
+    pub(crate) fn skips<Descr: 'static>(&self, description: &Descr) -> bool {
+        match self.0.as_ref().and_then(|predicate| predicate.downcast_ref::<Predicate<Descr>>()) {
+            Some(Predicate(predicate)) => predicate(description),
+            None => false,
+        }
+    }
+}
+
+impl<'a, Input, Output, K, S> Handler<'a, Input, Output, InterestSet<K, S>>
+where
+    Input: Send + 'a,
+    Output: 'a,
+    K: EventKind<S> + Eq + Hash + Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    /// Executes this handler with an event of the given kind.
+    ///
+    /// This is the same as [`Handler::dispatch`], except that the branches
+    /// (see [`Handler::branch`], [`Handler::branch_many`], and
+    /// [`crate::first_of`]) that are not interested in `kind`, i.e., whose
+    /// [`InterestSet::observed`] does not contain it, are skipped without
+    /// evaluating their filters. If this handler is not interested in `kind`
+    /// itself, the execution is continued right away.
+    ///
+    /// A subtree that does not observe `kind` can neither cause side effects
+    /// nor break the execution for it, so skipping it does not change the
+    /// result, provided that the descriptions are correct (see
+    /// [`InterestSet::new_filter`]). Note that [`InterestSet::filtered`] is
+    /// of no use here: it only tells what is passed to the handlers chained
+    /// to a subtree.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::collections::HashSet;
+    ///
+    /// use dptree::{
+    ///     description::{EventKind, InterestSet},
+    ///     prelude::*,
+    /// };
+    ///
+    /// #[derive(Clone, Copy, PartialEq, Eq, Hash)]
+    /// enum Kind {
+    ///     Message,
+    ///     Callback,
+    /// }
+    ///
+    /// impl EventKind for Kind {
+    ///     fn full_set() -> HashSet<Self> {
+    ///         [Kind::Message, Kind::Callback].iter().copied().collect()
+    ///     }
+    ///
+    ///     fn empty_set() -> HashSet<Self> {
+    ///         HashSet::new()
+    ///     }
+    /// }
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let messages = [Kind::Message].iter().copied().collect();
+    /// let handler: Handler<_, _, InterestSet<Kind>> = dptree::entry()
+    ///     .branch(
+    ///         dptree::filter_with_description(InterestSet::new_filter(messages), |text: &str| {
+    ///             !text.is_empty()
+    ///         })
+    ///         .endpoint(|| async { "message" }),
+    ///     )
+    ///     .branch(dptree::endpoint(|| async { "other" }));
+    ///
+    /// // The first branch is skipped, so `&str` is not even requested.
+    /// assert_eq!(
+    ///     handler.dispatch_with_kind(Kind::Callback, dptree::deps![]).await,
+    ///     ControlFlow::Break("other")
+    /// );
+    /// # }
+    /// ```
+    pub async fn dispatch_with_kind(
+        &self,
+        kind: K,
+        container: Input,
+    ) -> ControlFlow<Output, Input> {
+        let skips = move |description: &InterestSet<K, S>| !description.observed.contains(&kind);
+
+        if skips(self.description()) {
+            return ControlFlow::Continue(container);
+        }
+
+        let ctx = Context::new(Env::skipping(Skip::new(skips)));
+        self.dispatch_in(container, ctx).await
+    }
+
+    /// Executes this handler with the kind of `container` determined by
+    /// [`Classify`].
+    ///
+    /// See [`Handler::dispatch_with_kind`].
+    pub async fn dispatch_classified(&self, container: Input) -> ControlFlow<Output, Input>
+    where
+        Input: Classify<K>,
+    {
+        self.dispatch_with_kind(container.classify(), container).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashSet,
+        sync::atomic::{AtomicU32, Ordering},
+    };
+
+    use maplit::hashset;
+
+    use super::*;
+    use crate::{
+        deps,
+        di::{DependencyMap, DependencySupplier},
+        entry, filter_with_description, first_of,
+    };
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    enum Kind {
+        A,
+        B,
+    }
+
+    impl EventKind for Kind {
+        fn full_set() -> HashSet<Self> {
+            hashset! { Kind::A, Kind::B }
+        }
+
+        fn empty_set() -> HashSet<Self> {
+            hashset! {}
+        }
+    }
+
+    impl Classify<Kind> for DependencyMap {
+        fn classify(&self) -> Kind {
+            *self.get()
+        }
+    }
+
+    #[tokio::test]
+    async fn dispatch_with_kind() {
+        static EVALUATED: AtomicU32 = AtomicU32::new(0);
+
+        let only = |kind: Kind| -> Handler<DependencyMap, i32, InterestSet<Kind>> {
+            filter_with_description(InterestSet::new_filter(hashset! { kind }), move |k: Kind| {
+                EVALUATED.fetch_add(1, Ordering::Relaxed);
+                k == kind
+            })
+        };
+        let handler = entry()
+            .branch(only(Kind::A).endpoint(|x: i32| async move { x }))
+            .branch(first_of(vec![only(Kind::B).endpoint(|x: i32| async move { -x })]));
+
+        assert_eq!(handler.dispatch_classified(deps![Kind::B, 1]).await, ControlFlow::Break(-1));
+        assert_eq!(EVALUATED.load(Ordering::Relaxed), 1);
+        assert_eq!(handler.dispatch(deps![Kind::B, 1]).await, ControlFlow::Break(-1));
+        assert_eq!(EVALUATED.load(Ordering::Relaxed), 3);
+
+        // No subtree is interested in the kind.
+        let handler = only(Kind::A).endpoint(|| async { 0 });
+        assert_eq!(
+            handler.dispatch_with_kind(Kind::B, deps![Kind::A]).await,
+            ControlFlow::Continue(deps![Kind::A])
+        );
+        assert_eq!(EVALUATED.load(Ordering::Relaxed), 3);
+
+        // The dispatches started by the handlers skip nothing.
+        let inner = only(Kind::A).endpoint(|| async { 1 });
+        let handler = only(Kind::B).endpoint(move || {
+            let inner = inner.clone();
+            async move {
+                match inner.dispatch(deps![Kind::A]).await {
+                    ControlFlow::Break(x) => x,
+                    ControlFlow::Continue(_) => 0,
+                }
+            }
+        });
+        assert_eq!(
+            handler.dispatch_with_kind(Kind::B, deps![Kind::B]).await,
+            ControlFlow::Break(1)
+        );
+    }
+}
@@ -1,8 +1,16 @@
 use crate::{
+    description::NodeKind,
     di::{Asyncify, Injectable, Insert},
-    from_fn_with_description, Handler, HandlerDescription,
+    handler::{
+        compile::Step,
+        core::from_step,
+        error::inject,
+        on_error::raise,
+        trace::{self, NodeInfo, Verdict},
+    },
+    Handler, HandlerDescription,
 };
-use std::{ops::ControlFlow, sync::Arc};
+use std::sync::Arc;
 
 /// Constructs a handler that passes a value of a new type further.
 ///
@@ -45,6 +53,7 @@ where
 
 /// [`map`] with a custom description.
 #[must_use]
+#[track_caller]
 pub fn map_with_description<'a, Projection, Input, Output, NewType, Args, Descr>(
     description: Descr,
     proj: Projection,
@@ -62,6 +71,7 @@ where
 
 /// [`map_async`] with a custom description.
 #[must_use]
+#[track_caller]
 pub fn map_async_with_description<'a, Projection, Input, Output, NewType, Args, Descr>(
     description: Descr,
     proj: Projection,
@@ -74,21 +84,136 @@ where
     Descr: HandlerDescription,
     NewType: Send,
 {
+    let description = description.with_dependencies(
+        <Projection as Injectable<Input, NewType, Args>>::input_types(),
+        <Input as Insert<NewType>>::inserted_type().into_iter().collect(),
+    );
+    let node = NodeInfo::new(NodeKind::Map);
     let proj = Arc::new(proj);
 
-    from_fn_with_description(description, move |container: Input, cont| {
+    from_step(description, node, move |container: Input, env| {
         let proj = Arc::clone(&proj);
 
         async move {
-            let proj = proj.inject(&container);
-            let res = proj().await;
+            let Some(proj) = inject(&*proj, &container, &env) else {
+                return Step::Abort(container);
+            };
+            let res = trace::evaluate(&env, node, proj(), |_| Verdict::Pass).await;
             std::mem::drop(proj);
 
-            let mut intermediate = container.clone();
-            intermediate.insert(res);
-            match cont(intermediate).await {
-                ControlFlow::Continue(_) => ControlFlow::Continue(container),
-                ControlFlow::Break(result) => ControlFlow::Break(result),
+            let mut next = container.clone();
+            next.insert(res);
+            Step::Scoped { next, restore: container }
+        }
+    })
+}
+
+/// Constructs a handler that passes a value of a new type further, or fails.
+///
+/// If `proj` returns `Ok(v)`, then `v` will be added to the container and
+/// passed further in a handler chain. If it returns `Err(e)`, then `e` will be
+/// added to a copy of the container and routed to the innermost error handler;
+/// if there is none, the dispatch fails. See [`Handler::on_error`].
+#[must_use]
+#[track_caller]
+pub fn map_result<'a, Projection, Input, Output, NewType, E, Args, Descr>(
+    proj: Projection,
+) -> Handler<'a, Input, Output, Descr>
+where
+    Input: Clone,
+    Asyncify<Projection>: Injectable<Input, Result<NewType, E>, Args> + Send + Sync + 'a,
+    Input: Insert<NewType> + Insert<E> + Send + 'a,
+    Output: 'a,
+    Descr: HandlerDescription,
+    NewType: Send,
+    E: Send,
+{
+    map_result_with_description(Descr::map(), proj)
+}
+
+/// The asynchronous version of [`map_result`].
+#[must_use]
+#[track_caller]
+pub fn map_result_async<'a, Projection, Input, Output, NewType, E, Args, Descr>(
+    proj: Projection,
+) -> Handler<'a, Input, Output, Descr>
+where
+    Input: Clone,
+    Projection: Injectable<Input, Result<NewType, E>, Args> + Send + Sync + 'a,
+    Input: Insert<NewType> + Insert<E> + Send + 'a,
+    Output: 'a,
+    Descr: HandlerDescription,
+    NewType: Send,
+    E: Send,
+{
+    map_result_async_with_description(Descr::map_async(), proj)
+}
+
+/// [`map_result`] with a custom description.
+#[must_use]
+#[track_caller]
+pub fn map_result_with_description<'a, Projection, Input, Output, NewType, E, Args, Descr>(
+    description: Descr,
+    proj: Projection,
+) -> Handler<'a, Input, Output, Descr>
+where
+    Input: Clone,
+    Asyncify<Projection>: Injectable<Input, Result<NewType, E>, Args> + Send + Sync + 'a,
+    Input: Insert<NewType> + Insert<E> + Send + 'a,
+    Output: 'a,
+    Descr: HandlerDescription,
+    NewType: Send,
+    E: Send,
+{
+    map_result_async_with_description(description, Asyncify(proj))
+}
+
+/// [`map_result_async`] with a custom description.
+#[must_use]
+#[track_caller]
+pub fn map_result_async_with_description<'a, Projection, Input, Output, NewType, E, Args, Descr>(
+    description: Descr,
+    proj: Projection,
+) -> Handler<'a, Input, Output, Descr>
+where
+    Input: Clone,
+    Projection: Injectable<Input, Result<NewType, E>, Args> + Send + Sync + 'a,
+    Input: Insert<NewType> + Insert<E> + Send + 'a,
+    Output: 'a,
+    Descr: HandlerDescription,
+    NewType: Send,
+    E: Send,
+{
+    let description = description
+        .with_dependencies(
+            <Projection as Injectable<Input, Result<NewType, E>, Args>>::input_types(),
+            <Input as Insert<NewType>>::inserted_type().into_iter().collect(),
+        )
+        .with_errors(<Input as Insert<E>>::inserted_type().into_iter().collect());
+    let node = NodeInfo::new(NodeKind::Map);
+    let proj = Arc::new(proj);
+
+    from_step(description, node, move |container: Input, env| {
+        let proj = Arc::clone(&proj);
+
+        async move {
+            let Some(proj) = inject(&*proj, &container, &env) else {
+                return Step::Abort(container);
+            };
+            let res = trace::evaluate(&env, node, proj(), |res| match res {
+                Ok(_) => Verdict::Pass,
+                Err(_) => Verdict::Fail,
+            })
+            .await;
+            std::mem::drop(proj);
+
+            match res {
+                Ok(new_type) => {
+                    let mut next = container.clone();
+                    next.insert(new_type);
+                    Step::Scoped { next, restore: container }
+                }
+                Err(error) => raise(container, error),
             }
         }
     })
@@ -96,6 +221,8 @@ where
 
 #[cfg(test)]
 mod tests {
+    use std::ops::ControlFlow;
+
     use super::*;
     use crate::{deps, help_inference};
 
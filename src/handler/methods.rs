@@ -80,6 +80,67 @@ where
         self.chain(crate::map_async(proj))
     }
 
+    /// Chain this handler with the fallible filter projection `proj`.
+    #[must_use]
+    #[track_caller]
+    pub fn filter_map_result<Proj, NewType, E, Args>(
+        self,
+        proj: Proj,
+    ) -> Handler<'a, Input, Output, Descr>
+    where
+        Input: Insert<NewType> + Insert<E> + Clone,
+        Asyncify<Proj>: Injectable<Input, Result<Option<NewType>, E>, Args> + Send + Sync + 'a,
+        NewType: Send,
+        E: Send,
+    {
+        self.chain(crate::filter_map_result(proj))
+    }
+
+    /// Chain this handler with the async fallible filter projection `proj`.
+    #[must_use]
+    #[track_caller]
+    pub fn filter_map_result_async<Proj, NewType, E, Args>(
+        self,
+        proj: Proj,
+    ) -> Handler<'a, Input, Output, Descr>
+    where
+        Input: Insert<NewType> + Insert<E> + Clone,
+        Proj: Injectable<Input, Result<Option<NewType>, E>, Args> + Send + Sync + 'a,
+        NewType: Send,
+        E: Send,
+    {
+        self.chain(crate::filter_map_result_async(proj))
+    }
+
+    /// Chain this handler with the fallible map projection `proj`.
+    #[must_use]
+    #[track_caller]
+    pub fn map_result<Proj, NewType, E, Args>(self, proj: Proj) -> Handler<'a, Input, Output, Descr>
+    where
+        Input: Insert<NewType> + Insert<E> + Clone,
+        Asyncify<Proj>: Injectable<Input, Result<NewType, E>, Args> + Send + Sync + 'a,
+        NewType: Send,
+        E: Send,
+    {
+        self.chain(crate::map_result(proj))
+    }
+
+    /// Chain this handler with the async fallible map projection `proj`.
+    #[must_use]
+    #[track_caller]
+    pub fn map_result_async<Proj, NewType, E, Args>(
+        self,
+        proj: Proj,
+    ) -> Handler<'a, Input, Output, Descr>
+    where
+        Input: Insert<NewType> + Insert<E> + Clone,
+        Proj: Injectable<Input, Result<NewType, E>, Args> + Send + Sync + 'a,
+        NewType: Send,
+        E: Send,
+    {
+        self.chain(crate::map_result_async(proj))
+    }
+
     /// Chain this handler with the inspection function `f`.
     #[must_use]
     #[track_caller]
@@ -109,6 +170,19 @@ where
     {
         self.chain(crate::endpoint(f))
     }
+
+    /// Chain this handler with the fallible endpoint handler `f`.
+    #[must_use]
+    #[track_caller]
+    pub fn endpoint_result<F, E, FnArgs>(self, f: F) -> Handler<'a, Input, Output, Descr>
+    where
+        Input: Insert<E> + Clone,
+        Output: Send,
+        F: Injectable<Input, Result<Output, E>, FnArgs> + Send + Sync + 'a,
+        E: Send,
+    {
+        self.chain(crate::endpoint_result(f))
+    }
 }
 
 #[cfg(test)]
@@ -146,6 +220,26 @@ mod tests {
             .dispatch(deps![value])
             .await;
 
+        let _: ControlFlow<(), _> = help_inference(crate::entry())
+            .filter_map_result(|| Ok::<_, ()>(Some("abc")))
+            .dispatch(deps![value])
+            .await;
+
+        let _: ControlFlow<(), _> = help_inference(crate::entry())
+            .filter_map_result_async(|| async { Ok::<_, ()>(Some("abc")) })
+            .dispatch(deps![value])
+            .await;
+
+        let _: ControlFlow<(), _> = help_inference(crate::entry())
+            .map_result(|| Ok::<_, ()>("abc"))
+            .dispatch(deps![value])
+            .await;
+
+        let _: ControlFlow<(), _> = help_inference(crate::entry())
+            .map_result_async(|| async { Ok::<_, ()>("abc") })
+            .dispatch(deps![value])
+            .await;
+
         let _: ControlFlow<(), _> =
             help_inference(crate::entry()).inspect(|| {}).dispatch(deps![value]).await;
 
@@ -154,5 +248,10 @@ mod tests {
 
         let _: ControlFlow<(), _> =
             help_inference(crate::entry()).endpoint(|| async {}).dispatch(deps![value]).await;
+
+        let _: ControlFlow<(), _> = help_inference(crate::entry())
+            .endpoint_result(|| async { Ok::<_, ()>(()) })
+            .dispatch(deps![value])
+            .await;
     }
 }
@@ -0,0 +1,257 @@
+//! Observing dispatches for collecting metrics.
+//!
+//! See [`Handler::observe`](crate::Handler::observe).
+
+use std::{
+    collections::HashMap,
+    ops::ControlFlow,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    description::NodeKind,
+    handler::{
+        context::Env,
+        core::from_fn_with_context,
+        trace::{NodeInfo, Verdict},
+    },
+    Handler, HandlerDescription,
+};
+
+/// A receiver of the events happening during a dispatch.
+///
+/// All methods do nothing by default. They are called synchronously from the
+/// dispatch future, so they should be cheap.
+pub trait DispatchObserver: Send + Sync {
+    /// A handler has been entered.
+    fn node_entered(&self, node: NodeInfo) {
+        let _ = node;
+    }
+
+    /// A filter (i.e., [`crate::filter`] or [`crate::filter_map`]) has made a
+    /// decision, having spent `elapsed` in its injected function.
+    fn filter_verdict(&self, node: NodeInfo, verdict: Verdict, elapsed: Duration) {
+        let _ = (node, verdict, elapsed);
+    }
+
+    /// An endpoint has completed, having spent `elapsed` in its injected
+    /// function.
+    fn endpoint_completed(&self, node: NodeInfo, elapsed: Duration) {
+        let _ = (node, elapsed);
+    }
+
+    /// The observed handler has finished its execution in `elapsed`. `handled`
+    /// is `true` if it has returned [`ControlFlow::Break`].
+    fn dispatch_finished(&self, handled: bool, elapsed: Duration) {
+        let _ = (handled, elapsed);
+    }
+}
+
+/// The observers of the handlers that are being executed, from the outermost;
+/// `None` if there are none.
+pub(crate) type Observers = Option<Arc<[Arc<dyn DispatchObserver>]>>;
+
+/// Calls `f` on every observer of `env`.
+fn notify(env: &Env, f: impl Fn(&dyn DispatchObserver)) {
+    for observer in env.observers.iter().flat_map(|observers| observers.iter()) {
+        f(&**observer);
+    }
+}
+
+pub(crate) fn entered(env: &Env, node: NodeInfo) {
+    notify(env, |observer| observer.node_entered(node));
+}
+
+pub(crate) fn evaluated(env: &Env, node: NodeInfo, verdict: Verdict, elapsed: Duration) {
+    match node.kind {
+        NodeKind::Filter | NodeKind::FilterMap => {
+            notify(env, |observer| observer.filter_verdict(node, verdict, elapsed))
+        }
+        NodeKind::Endpoint => notify(env, |observer| observer.endpoint_completed(node, elapsed)),
+        _ => {}
+    }
+}
+
+impl<'a, Input, Output, Descr> Handler<'a, Input, Output, Descr>
+where
+    Input: Send + 'a,
+    Output: 'a,
+    Descr: HandlerDescription + Clone,
+{
+    /// Attaches `observer` to this handler.
+    ///
+    /// The observer is notified about every handler executed as a part of
+    /// this handler, and about the completion of this handler (including its
+    /// continuation). The handlers in the continuation (e.g., `b` in
+    /// `a.observe(o).chain(b)`) are not observed, since they do not belong to
+    /// this handler, nor are the dispatches that the handlers start on their
+    /// own (e.g., with [`Handler::dispatch`]). The description of the resulting handler is the same as
+    /// of this one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::sync::Arc;
+    ///
+    /// use dptree::{observer::CountingObserver, prelude::*};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let counter = Arc::new(CountingObserver::new());
+    /// let handler: Handler<_, _> = dptree::filter(|x: i32| x > 0)
+    ///     .endpoint(|| async { "positive" })
+    ///     .observe(Arc::clone(&counter));
+    ///
+    /// let _ = handler.dispatch(dptree::deps![1]).await;
+    /// let _ = handler.dispatch(dptree::deps![-1]).await;
+    ///
+    /// let counts = counter.counts();
+    /// assert_eq!(counts.dispatches, 2);
+    /// assert_eq!(counts.handled, 1);
+    /// # }
+    /// ```
+    #[must_use]
+    pub fn observe<O>(self, observer: Arc<O>) -> Self
+    where
+        O: DispatchObserver + 'static,
+    {
+        let observer: Arc<dyn DispatchObserver> = observer;
+
+        from_fn_with_context(self.description().clone(), None, move |event, cont, mut ctx| {
+            let this = self.clone();
+            let observer = Arc::clone(&observer);
+
+            async move {
+                let start = Instant::now();
+                let outer = ctx.env.observers.iter().flat_map(|observers| observers.iter());
+                ctx.env.observers =
+                    Some(outer.cloned().chain(Some(Arc::clone(&observer))).collect());
+
+                let result = this.execute_in(event, cont, ctx).await;
+                let handled = matches!(result, ControlFlow::Break(_));
+                observer.dispatch_finished(handled, start.elapsed());
+                result
+            }
+        })
+    }
+}
+
+/// A [`DispatchObserver`] that counts the events it receives.
+///
+/// Handlers are identified by [`NodeInfo`], i.e., by their kinds and the
+/// locations where they were constructed.
+#[derive(Debug, Default)]
+pub struct CountingObserver {
+    counts: Mutex<Counts>,
+}
+
+/// A snapshot of [`CountingObserver`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Counts {
+    /// How many times each handler has been entered.
+    pub entered: HashMap<NodeInfo, u64>,
+
+    /// How many times each filter has made each decision.
+    pub verdicts: HashMap<(NodeInfo, Verdict), u64>,
+
+    /// How many times each endpoint has completed and the total time spent in
+    /// it.
+    pub endpoints: HashMap<NodeInfo, (u64, Duration)>,
+
+    /// The number of finished dispatches.
+    pub dispatches: u64,
+
+    /// The number of finished dispatches that have been handled.
+    pub handled: u64,
+}
+
+impl CountingObserver {
+    /// Creates an observer with all counts set to zero.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the counts collected so far.
+    pub fn counts(&self) -> Counts {
+        self.lock().clone()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Counts> {
+        // The counts are always consistent, even if some thread has panicked.
+        self.counts.lock().unwrap_or_else(|error| error.into_inner())
+    }
+}
+
+impl DispatchObserver for CountingObserver {
+    fn node_entered(&self, node: NodeInfo) {
+        *self.lock().entered.entry(node).or_default() += 1;
+    }
+
+    fn filter_verdict(&self, node: NodeInfo, verdict: Verdict, _elapsed: Duration) {
+        *self.lock().verdicts.entry((node, verdict)).or_default() += 1;
+    }
+
+    fn endpoint_completed(&self, node: NodeInfo, elapsed: Duration) {
+        let mut counts = self.lock();
+        let (completed, total) = counts.endpoints.entry(node).or_default();
+        *completed += 1;
+        *total += elapsed;
+    }
+
+    fn dispatch_finished(&self, handled: bool, _elapsed: Duration) {
+        let mut counts = self.lock();
+        counts.dispatches += 1;
+        counts.handled += u64::from(handled);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{deps, endpoint, entry, filter, filter_map, help_inference};
+
+    #[tokio::test]
+    async fn counting_observer() {
+        let counter = Arc::new(CountingObserver::new());
+        let handler = help_inference(entry())
+            .branch(filter(|x: i32| x > 0).endpoint(|| async { "positive" }))
+            .branch(filter_map(|x: i32| x.checked_neg()).endpoint(|| async { "negated" }))
+            .observe(Arc::clone(&counter));
+
+        for x in [1, -1, i32::MIN].iter() {
+            let _ = handler.dispatch(deps![*x]).await;
+        }
+
+        let counts = counter.counts();
+        assert_eq!((counts.dispatches, counts.handled), (3, 2));
+
+        let verdicts = |kind, verdict| -> u64 {
+            counts
+                .verdicts
+                .iter()
+                .filter(|((node, v), _)| node.kind == kind && *v == verdict)
+                .map(|(_, count)| count)
+                .sum()
+        };
+        assert_eq!(verdicts(NodeKind::Filter, Verdict::Pass), 1);
+        assert_eq!(verdicts(NodeKind::Filter, Verdict::Reject), 2);
+        assert_eq!(verdicts(NodeKind::FilterMap, Verdict::Pass), 1);
+        assert_eq!(verdicts(NodeKind::FilterMap, Verdict::Reject), 1);
+
+        let mut endpoints: Vec<_> = counts.endpoints.values().map(|(n, _)| *n).collect();
+        endpoints.sort_unstable();
+        assert_eq!(endpoints, [1, 1]);
+
+        // The dispatches started by the handlers are not observed.
+        let outer = Arc::new(CountingObserver::new());
+        let nested = help_inference(endpoint(move |x: i32| {
+            let handler = handler.clone();
+            async move { handler.dispatch(deps![x]).await.is_break() }
+        }))
+        .observe(Arc::clone(&outer));
+        assert_eq!(nested.dispatch(deps![1]).await, ControlFlow::Break(true));
+        assert!(outer.counts().verdicts.is_empty());
+        assert_eq!(counter.counts().dispatches, 4);
+    }
+}
@@ -0,0 +1,224 @@
+//! Routing of the errors returned by fallible handlers.
+//!
+//! See [`crate::Handler::on_error`].
+
+use std::{ops::ControlFlow, sync::Arc};
+
+use crate::{
+    di::Insert,
+    handler::{
+        compile::Step,
+        context::Context,
+        core::{from_fn_with_context, HandlerResult},
+        error::DispatchError,
+    },
+    Handler, HandlerDescription,
+};
+
+/// The error handler of the subtree that is being executed (see [`Context`]).
+pub(crate) type Route<'a, Input, Output> =
+    Option<Arc<dyn Fn(Input) -> HandlerResult<'a, Input, Output> + Send + Sync + 'a>>;
+
+/// Fails the handler with `error`, which is inserted into a copy of
+/// `container`.
+pub(crate) fn raise<Input, Output, E>(container: Input, error: E) -> Step<Output, Input>
+where
+    Input: Insert<E> + Clone,
+{
+    let mut failed = container.clone();
+    failed.insert(error);
+    Step::Fail { event: container, failed, type_name: std::any::type_name::<E>() }
+}
+
+/// Dispatches `failed`, the container of a failed handler with the error, to
+/// the error handler, continuing the execution with `event` unless it breaks.
+///
+/// If there is no error handler, the dispatch fails with
+/// [`DispatchError::UnhandledError`] (see [`Env::fail`]).
+///
+/// [`Env::fail`]: crate::handler::context::Env::fail
+pub(crate) async fn route<'a, Input, Output>(
+    ctx: &Context<'a, Input, Output>,
+    event: Input,
+    failed: Input,
+    type_name: &'static str,
+) -> ControlFlow<Output, Input> {
+    let route = match &ctx.route {
+        Some(route) => route,
+        None => {
+            ctx.env.fail(DispatchError::UnhandledError { type_name });
+            return ControlFlow::Continue(event);
+        }
+    };
+
+    match route(failed).await {
+        ControlFlow::Break(output) => ControlFlow::Break(output),
+        ControlFlow::Continue(_) => ControlFlow::Continue(event),
+    }
+}
+
+impl<'a, Input, Output, Descr> Handler<'a, Input, Output, Descr>
+where
+    Input: Send + 'a,
+    Output: 'a,
+    Descr: HandlerDescription,
+{
+    /// Routes the errors returned by the fallible handlers in this subtree
+    /// (such as [`crate::map_result`]) to `handler`.
+    ///
+    /// When a fallible handler returns `Err(error)`, `error` is inserted into
+    /// a copy of the container of the failed handler, and the copy is
+    /// dispatched to `handler`. If `handler` breaks the execution, so does the
+    /// failed handler; otherwise, the failed handler behaves as if it has
+    /// rejected the event, continuing the execution with its own container
+    /// (so the error cannot be injected outside of `handler`). The errors of `handler` itself are routed to outer error
+    /// handlers, as are the errors of the handlers in the continuation of this
+    /// handler (e.g., `b` in `a.on_error(h).chain(b)`), since they do not
+    /// belong to the subtree.
+    ///
+    /// If an error is not routed to any error handler, the dispatch fails, as
+    /// it does when a dependency is missing: [`Handler::dispatch`] panics, and
+    /// [`Handler::try_dispatch`] returns [`DispatchError::UnhandledError`].
+    ///
+    /// Errors are only routed through built-in handlers: the handlers that a
+    /// user-defined handler executes on its own (e.g., with
+    /// [`Handler::dispatch`]) route their errors within that execution only.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dptree::prelude::*;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let handler: Handler<_, _> = dptree::map_result(|s: &'static str| s.parse::<i32>())
+    ///     .endpoint(|x: i32| async move { format!("parsed {}", x) })
+    ///     .on_error(dptree::endpoint(|error: std::num::ParseIntError| async move {
+    ///         format!("failed: {}", error)
+    ///     }));
+    ///
+    /// assert_eq!(handler.dispatch(dptree::deps!["1"]).await, ControlFlow::Break("parsed 1".to_owned()));
+    /// assert_eq!(
+    ///     handler.dispatch(dptree::deps!["x"]).await,
+    ///     ControlFlow::Break("failed: invalid digit found in string".to_owned())
+    /// );
+    /// # }
+    /// ```
+    #[must_use]
+    pub fn on_error(self, handler: Self) -> Self {
+        let description = self.description().on_error(handler.description());
+
+        from_fn_with_context(description, None, move |event, cont, outer: Context<'a, _, _>| {
+            let handler = handler.clone();
+            let env = outer.env.clone();
+            let route: Route<'a, Input, Output> = Some(Arc::new(move |event| {
+                let handler = handler.clone();
+                let outer = outer.clone();
+                Box::pin(async move { handler.dispatch_in(event, outer).await })
+            }));
+
+            self.clone().execute_in(event, cont, Context { route, env })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ops::ControlFlow, sync::Arc};
+
+    use crate::{
+        deps,
+        di::{DependencySupplier, Insert},
+        endpoint, endpoint_result, entry, filter, help_inference, map_result, DispatchError,
+        Handler,
+    };
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Error(&'static str);
+
+    #[tokio::test]
+    async fn on_error() {
+        let parse = help_inference(map_result(|s: &'static str| match s.parse::<i32>() {
+            Ok(x) => Ok(x),
+            Err(_) => Err(Error("inner")),
+        }));
+
+        let inner = parse
+            .endpoint(|x: i32| async move { format!("{}", x) })
+            .on_error(endpoint(|e: Error| async move { e.0.to_owned() }));
+        assert_eq!(inner.dispatch(deps!["1"]).await, ControlFlow::Break("1".to_owned()));
+        assert_eq!(inner.dispatch(deps!["x"]).await, ControlFlow::Break("inner".to_owned()));
+
+        // Errors raised in the continuation are routed to the outer handler.
+        let handler = help_inference(entry())
+            .chain(entry().on_error(endpoint(|| async { "inner".to_owned() })))
+            .chain(map_result(|| Err::<(), _>(Error("outer"))))
+            .endpoint(|| async { unreachable!() })
+            .on_error(endpoint(|e: Error| async move { e.0.to_owned() }));
+        assert_eq!(handler.dispatch(deps![]).await, ControlFlow::Break("outer".to_owned()));
+
+        // If the error handler continues the execution, the failed handler
+        // behaves as if it has rejected the event, whose container has no
+        // error.
+        let handler = help_inference(entry())
+            .branch(
+                map_result(|| Err::<(), _>(Error("skipped"))).endpoint(|| async { unreachable!() }),
+            )
+            .branch(endpoint(|| async { "next".to_owned() }))
+            .on_error(filter(|e: Error| e.0 != "skipped"));
+        assert_eq!(handler.dispatch(deps![]).await, ControlFlow::Break("next".to_owned()));
+        let handler = help_inference(map_result(|| Err::<(), _>(Error("skipped"))))
+            .endpoint(|| async { unreachable!() })
+            .on_error(filter(|| false));
+        assert_eq!(handler.dispatch(deps![]).await, ControlFlow::<String, _>::Continue(deps![]));
+
+        // An unhandled error fails the dispatch.
+        let unhandled = help_inference(map_result(|| Err::<(), _>(Error("unhandled"))))
+            .endpoint(|| async { unreachable!() });
+        assert_eq!(
+            unhandled.try_dispatch(deps![]).await,
+            Err::<ControlFlow<(), _>, _>(DispatchError::UnhandledError {
+                type_name: std::any::type_name::<Error>()
+            })
+        );
+        let panicked = tokio::spawn(async move { unhandled.dispatch(deps![]).await });
+        assert!(panicked.await.expect_err("The dispatch must panic").is_panic());
+    }
+
+    #[tokio::test]
+    async fn borrowed_input() {
+        #[derive(Clone)]
+        struct Request<'s> {
+            text: &'s str,
+            error: Option<Arc<Error>>,
+        }
+
+        impl<'s> DependencySupplier<&'s str> for Request<'s> {
+            fn get(&self) -> Arc<&'s str> {
+                Arc::new(self.text)
+            }
+        }
+
+        impl DependencySupplier<Error> for Request<'_> {
+            fn get(&self) -> Arc<Error> {
+                Arc::clone(self.error.as_ref().unwrap())
+            }
+        }
+
+        impl Insert<Error> for Request<'_> {
+            fn insert(&mut self, error: Error) -> Option<Arc<Error>> {
+                self.error.replace(Arc::new(error))
+            }
+        }
+
+        let text = String::from("x");
+        let handler: Handler<Request<'_>, i32> = endpoint_result(|text: &str| {
+            let parsed = text.parse().map_err(|_| Error("not a number"));
+            async move { parsed }
+        })
+        .on_error(endpoint(|e: Error| async move { e.0.len() as i32 }));
+
+        let request = Request { text: &text, error: None };
+        assert!(matches!(handler.dispatch(request).await, ControlFlow::Break(12)));
+    }
+}
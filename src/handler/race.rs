@@ -0,0 +1,187 @@
+use std::{ops::ControlFlow, sync::Arc};
+
+use futures::{stream::FuturesUnordered, StreamExt};
+
+use crate::{handler::core::from_fn_with_context, Handler, HandlerDescription};
+
+/// Constructs a handler that executes the handlers of `children`
+/// concurrently, breaking the execution with the output of the first one
+/// that breaks.
+///
+/// As soon as some child breaks, the other children are cancelled, i.e.,
+/// their futures are dropped. If no child breaks, the execution continues.
+/// This is useful when the branches are guarded by slow asynchronous filters
+/// (e.g., [`crate::filter_async`] that query a database): unlike
+/// [`Handler::branch`], the total latency is not the sum of their latencies.
+///
+/// If several children may break, and the declaration order matters, use
+/// [`race_ordered`].
+///
+/// The description is merged as if the children were branched from
+/// [`crate::entry`].
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use dptree::prelude::*;
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let children: Vec<Handler<_, _>> = vec![
+///     dptree::filter_async(|| async {
+///         tokio::time::sleep(Duration::from_secs(60)).await;
+///         true
+///     })
+///     .endpoint(|| async { "slow" }),
+///     dptree::filter(|x: i32| x > 0).endpoint(|| async { "fast" }),
+/// ];
+/// let handler = dptree::race(children);
+///
+/// assert_eq!(handler.dispatch(dptree::deps![1]).await, ControlFlow::Break("fast"));
+/// # }
+/// ```
+#[must_use]
+#[track_caller]
+pub fn race<'a, Input, Output, Descr, I>(children: I) -> Handler<'a, Input, Output, Descr>
+where
+    I: IntoIterator<Item = Handler<'a, Input, Output, Descr>>,
+    Input: Clone + Send + 'a,
+    Output: Send + 'a,
+    Descr: HandlerDescription,
+{
+    race_impl(children.into_iter().collect(), false)
+}
+
+/// Like [`race`], but if several children break, the output of the first one
+/// in the declaration order is chosen.
+///
+/// The children are still executed concurrently. A child that breaks wins
+/// as soon as all the children declared before it have continued the
+/// execution; then, the remaining children are cancelled.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use dptree::prelude::*;
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let children: Vec<Handler<_, _>> = vec![
+///     dptree::filter_async(|x: i32| async move {
+///         tokio::time::sleep(Duration::from_millis(10)).await;
+///         x > 0
+///     })
+///     .endpoint(|| async { "positive" }),
+///     dptree::endpoint(|| async { "fallback" }),
+/// ];
+/// let handler = dptree::race_ordered(children);
+///
+/// assert_eq!(handler.dispatch(dptree::deps![1]).await, ControlFlow::Break("positive"));
+/// assert_eq!(handler.dispatch(dptree::deps![-1]).await, ControlFlow::Break("fallback"));
+/// # }
+/// ```
+#[must_use]
+#[track_caller]
+pub fn race_ordered<'a, Input, Output, Descr, I>(children: I) -> Handler<'a, Input, Output, Descr>
+where
+    I: IntoIterator<Item = Handler<'a, Input, Output, Descr>>,
+    Input: Clone + Send + 'a,
+    Output: Send + 'a,
+    Descr: HandlerDescription,
+{
+    race_impl(children.into_iter().collect(), true)
+}
+
+#[track_caller]
+fn race_impl<'a, Input, Output, Descr>(
+    children: Vec<Handler<'a, Input, Output, Descr>>,
+    ordered: bool,
+) -> Handler<'a, Input, Output, Descr>
+where
+    Input: Clone + Send + 'a,
+    Output: Send + 'a,
+    Descr: HandlerDescription,
+{
+    let description = children
+        .iter()
+        .fold(Descr::entry(), |description, child| description.merge_branch(child.description()));
+    let children: Arc<[_]> = children.into();
+
+    from_fn_with_context(description, None, move |event: Input, cont, ctx| {
+        let children = Arc::clone(&children);
+
+        async move {
+            let mut running: FuturesUnordered<_> = children
+                .iter()
+                .enumerate()
+                .map(|(i, child)| {
+                    let fut = child.dispatch_in(event.clone(), ctx.clone());
+                    async move { (i, fut.await) }
+                })
+                .collect();
+
+            // The results of the children that have finished before some of
+            // the preceding ones, for `ordered`.
+            let mut finished: Vec<Option<ControlFlow<Output, Input>>> =
+                (0..children.len()).map(|_| None).collect();
+            let mut next = 0;
+
+            while let Some((i, result)) = running.next().await {
+                if !ordered {
+                    if let ControlFlow::Break(output) = result {
+                        return ControlFlow::Break(output);
+                    }
+                    continue;
+                }
+
+                finished[i] = Some(result);
+                while let Some(result) = finished.get_mut(next).and_then(Option::take) {
+                    match result {
+                        ControlFlow::Break(output) => return ControlFlow::Break(output),
+                        ControlFlow::Continue(_) => next += 1,
+                    }
+                }
+            }
+
+            cont(event).await
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{deps, di::DependencyMap, endpoint, filter_async};
+
+    // The time is paused, so the sleeps finish in the order of their durations.
+    #[tokio::test(start_paused = true)]
+    async fn race() {
+        let delayed = |millis, output| -> Handler<DependencyMap, &'static str> {
+            filter_async(move |x: i32| async move {
+                tokio::time::sleep(Duration::from_millis(millis)).await;
+                x > 0
+            })
+            .endpoint(move || async move { output })
+        };
+        let children =
+            || vec![delayed(50, "first"), delayed(10, "second"), endpoint(|| async { "third" })];
+
+        let unordered = super::race(children());
+        assert_eq!(unordered.dispatch(deps![1]).await, ControlFlow::Break("third"));
+        let unordered = super::race(vec![delayed(50, "first"), delayed(10, "second")]);
+        assert_eq!(unordered.dispatch(deps![1]).await, ControlFlow::Break("second"));
+
+        let ordered = race_ordered(children());
+        assert_eq!(ordered.dispatch(deps![1]).await, ControlFlow::Break("first"));
+        assert_eq!(ordered.dispatch(deps![-1]).await, ControlFlow::Break("third"));
+
+        let none = super::race(vec![delayed(0, "positive")]);
+        assert_eq!(none.dispatch(deps![-1]).await, ControlFlow::Continue(deps![-1]));
+    }
+}
@@ -0,0 +1,428 @@
+//! Limiting the rate of events per key.
+//!
+//! See [`crate::Handler::rate_limit`].
+
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    ops::ControlFlow,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use futures::future::BoxFuture;
+
+use crate::{
+    description::NodeKind,
+    di::{Asyncify, Injectable},
+    handler::{
+        compile::Step,
+        context::Env,
+        core::{from_fn_with_context, from_step},
+        error::inject,
+        trace::{self, NodeInfo, Verdict},
+    },
+    Handler, HandlerDescription,
+};
+
+/// The number of events allowed per period of time.
+///
+/// The events are allowed at a steady rate, with bursts of up to the whole
+/// number of events after a period of inactivity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quota {
+    interval: Duration,
+    burst: u32,
+}
+
+impl Quota {
+    /// Allows `count` events per `period`.
+    ///
+    /// If `period` is so long that the time of the next allowed event cannot be
+    /// represented by [`Instant`], it never comes: e.g., `Quota::new(1,
+    /// Duration::MAX)` allows a single event per key.
+    ///
+    /// # Panics
+    ///
+    /// If `count` is zero or `period` is shorter than `count` nanoseconds.
+    #[must_use]
+    pub fn new(count: u32, period: Duration) -> Self {
+        assert!(count > 0, "The number of events must be positive");
+        let interval = period / count;
+        assert!(!interval.is_zero(), "The period must be at least one nanosecond per event");
+        Self { interval, burst: count }
+    }
+
+    /// Allows `count` events per second.
+    #[must_use]
+    pub fn per_second(count: u32) -> Self {
+        Self::new(count, Duration::from_secs(1))
+    }
+
+    /// Allows `count` events per minute.
+    #[must_use]
+    pub fn per_minute(count: u32) -> Self {
+        Self::new(count, Duration::from_secs(60))
+    }
+
+    /// Allows bursts of up to `burst` events, instead of the number of events
+    /// per period.
+    ///
+    /// # Panics
+    ///
+    /// If `burst` is zero.
+    #[must_use]
+    pub fn burst(self, burst: u32) -> Self {
+        assert!(burst > 0, "The burst must be positive");
+        Self { burst, ..self }
+    }
+
+    /// How much the theoretical arrival time of the next event may be ahead of
+    /// the current time, or `None` if it is too long to be represented.
+    fn tolerance(&self) -> Option<Duration> {
+        self.interval.checked_mul(self.burst - 1)
+    }
+}
+
+/// The source of time of [`RateLimiter`].
+pub trait Clock: Send + Sync {
+    /// The current time.
+    fn now(&self) -> Instant;
+}
+
+/// The clock of the system, i.e., [`Instant::now`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock that is advanced manually, for testing.
+///
+/// Clones of the clock share the current time.
+#[derive(Debug, Clone)]
+pub struct ManualClock(Arc<Mutex<Instant>>);
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ManualClock {
+    /// Constructs a clock stopped at the current time.
+    #[must_use]
+    pub fn new() -> Self {
+        Self(Arc::new(Mutex::new(Instant::now())))
+    }
+
+    /// Advances the clock by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        *self.0.lock().unwrap_or_else(|error| error.into_inner()) += duration;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        *self.0.lock().unwrap_or_else(|error| error.into_inner())
+    }
+}
+
+/// Limits the rate of events per key, according to [`Quota`].
+///
+/// This implements the generic cell rate algorithm (GCRA), which keeps only
+/// the theoretical arrival time of the next event for each key; the keys that
+/// are idle long enough to allow a whole burst are forgotten.
+///
+/// Clones of a rate limiter share the state, so a rate limiter can be used by
+/// several handlers to limit the events passing through all of them.
+pub struct RateLimiter<K> {
+    quota: Quota,
+    clock: Arc<dyn Clock>,
+    state: Arc<Mutex<State<K>>>,
+}
+
+struct State<K> {
+    /// The theoretical arrival times of the next events; `None` if it cannot
+    /// be represented, i.e., never.
+    arrivals: HashMap<K, Option<Instant>>,
+    /// The number of keys at which the idle keys are forgotten.
+    cleanup_at: usize,
+}
+
+const MIN_CLEANUP_AT: usize = 64;
+
+impl<K> Clone for RateLimiter<K> {
+    fn clone(&self) -> Self {
+        Self { quota: self.quota, clock: Arc::clone(&self.clock), state: Arc::clone(&self.state) }
+    }
+}
+
+impl<K> From<Quota> for RateLimiter<K>
+where
+    K: Hash + Eq,
+{
+    fn from(quota: Quota) -> Self {
+        Self::new(quota)
+    }
+}
+
+impl<K> RateLimiter<K>
+where
+    K: Hash + Eq,
+{
+    /// Constructs a rate limiter with [`SystemClock`].
+    #[must_use]
+    pub fn new(quota: Quota) -> Self {
+        Self::with_clock(quota, SystemClock)
+    }
+
+    /// Constructs a rate limiter with `clock`.
+    #[must_use]
+    pub fn with_clock<C>(quota: Quota, clock: C) -> Self
+    where
+        C: Clock + 'static,
+    {
+        let state = State { arrivals: HashMap::new(), cleanup_at: MIN_CLEANUP_AT };
+        Self { quota, clock: Arc::new(clock), state: Arc::new(Mutex::new(state)) }
+    }
+
+    /// Registers an event with `key`, returning whether it is within the quota.
+    ///
+    /// The events over the quota are not counted.
+    pub fn check(&self, key: K) -> bool {
+        let now = self.clock.now();
+        let mut state = self.state.lock().unwrap_or_else(|error| error.into_inner());
+
+        if state.arrivals.len() >= state.cleanup_at {
+            state.arrivals.retain(|_, arrival| arrival.map_or(true, |arrival| arrival > now));
+            state.cleanup_at = MIN_CLEANUP_AT.max(state.arrivals.len() * 2);
+        }
+
+        // The times that overflow `Instant` are `None`, i.e., never come.
+        let arrival = match state.arrivals.get(&key) {
+            Some(arrival) => arrival.map(|arrival| arrival.max(now)),
+            None => Some(now),
+        };
+        let latest = self.quota.tolerance().and_then(|tolerance| now.checked_add(tolerance));
+        let exceeded = match (arrival, latest) {
+            (_, None) => false,
+            (None, Some(_)) => true,
+            (Some(arrival), Some(latest)) => arrival > latest,
+        };
+        if exceeded {
+            return false;
+        }
+
+        let next = arrival.and_then(|arrival| arrival.checked_add(self.quota.interval));
+        state.arrivals.insert(key, next);
+        true
+    }
+}
+
+impl<'a, Input, Output, Descr> Handler<'a, Input, Output, Descr>
+where
+    Input: Send + 'a,
+    Output: 'a,
+    Descr: HandlerDescription,
+{
+    /// Chain this handler with a rate limiter of the events with the key
+    /// computed by `key`.
+    ///
+    /// The events within the quota of their key are passed further; the rest
+    /// are filtered out, i.e., the handler returns
+    /// [`ControlFlow::Continue`](std::ops::ControlFlow::Continue).
+    /// `limiter` is either [`Quota`] or [`RateLimiter`], which allows to set a
+    /// clock or to share the limits with other handlers.
+    ///
+    /// The rate limiter is described as [`crate::filter`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dptree::{prelude::*, rate_limit::Quota};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let handler: Handler<_, _> = dptree::entry()
+    ///     .rate_limit(|user: u64| user, Quota::per_minute(2))
+    ///     .endpoint(|| async { "Hello!" });
+    ///
+    /// assert_eq!(handler.dispatch(dptree::deps![1u64]).await, ControlFlow::Break("Hello!"));
+    /// assert_eq!(handler.dispatch(dptree::deps![1u64]).await, ControlFlow::Break("Hello!"));
+    /// assert_eq!(
+    ///     handler.dispatch(dptree::deps![1u64]).await,
+    ///     ControlFlow::Continue(dptree::deps![1u64])
+    /// );
+    /// assert_eq!(handler.dispatch(dptree::deps![2u64]).await, ControlFlow::Break("Hello!"));
+    /// # }
+    /// ```
+    #[must_use]
+    #[track_caller]
+    pub fn rate_limit<F, K, Args, L>(self, key: F, limiter: L) -> Self
+    where
+        Asyncify<F>: Injectable<Input, K, Args> + Send + Sync + 'a,
+        K: Hash + Eq + Send + 'a,
+        L: Into<RateLimiter<K>>,
+    {
+        let key = Asyncify(key);
+        let description = Descr::filter().with_dependencies(
+            <Asyncify<F> as Injectable<Input, K, Args>>::input_types(),
+            Vec::new(),
+        );
+        let node = NodeInfo::new(NodeKind::Filter);
+        let check = checker(key, limiter.into());
+
+        self.chain(from_step(description, node, move |event, env| {
+            let check = Arc::clone(&check);
+
+            async move {
+                match trace::evaluate(&env, node, check(event, env.clone()), |&(_, allowed)| {
+                    verdict(allowed)
+                })
+                .await
+                {
+                    (event, Some(true)) => Step::Pass(event),
+                    (event, Some(false)) => Step::Reject(event),
+                    (event, None) => Step::Abort(event),
+                }
+            }
+        }))
+    }
+
+    /// [`Handler::rate_limit`] that executes `exceeded` with the events over
+    /// the limit, instead of filtering them out.
+    ///
+    /// If `exceeded` continues the execution, the handler continues it as
+    /// well. `exceeded` is described as a branch of the rate limiter.
+    #[must_use]
+    #[track_caller]
+    pub fn rate_limit_or<F, K, Args, L>(self, key: F, limiter: L, exceeded: Self) -> Self
+    where
+        Asyncify<F>: Injectable<Input, K, Args> + Send + Sync + 'a,
+        K: Hash + Eq + Send + 'a,
+        L: Into<RateLimiter<K>>,
+    {
+        let key = Asyncify(key);
+        let description = Descr::filter()
+            .with_dependencies(
+                <Asyncify<F> as Injectable<Input, K, Args>>::input_types(),
+                Vec::new(),
+            )
+            .merge_branch(exceeded.description());
+        let node = NodeInfo::new(NodeKind::Filter);
+        let check = checker(key, limiter.into());
+
+        self.chain(from_fn_with_context(description, Some(node), move |event, cont, ctx| {
+            let check = Arc::clone(&check);
+            let exceeded = exceeded.clone();
+
+            async move {
+                let checked = check(event, ctx.env.clone());
+                match trace::evaluate(&ctx.env, node, checked, |&(_, allowed)| verdict(allowed))
+                    .await
+                {
+                    (event, Some(true)) => cont(event).await,
+                    (event, Some(false)) => exceeded.dispatch_in(event, ctx).await,
+                    (event, None) => ControlFlow::Continue(event),
+                }
+            }
+        }))
+    }
+}
+
+type Checker<'a, Input> =
+    dyn Fn(Input, Env) -> BoxFuture<'a, (Input, Option<bool>)> + Send + Sync + 'a;
+
+/// Computes the key of an event with `key` and checks it with `limiter`.
+///
+/// The checker returns `None` if the dispatch has failed.
+fn checker<'a, F, Input, K, Args>(key: F, limiter: RateLimiter<K>) -> Arc<Checker<'a, Input>>
+where
+    F: Injectable<Input, K, Args> + Send + Sync + 'a,
+    Input: Send + 'a,
+    K: Hash + Eq + Send + 'a,
+{
+    let key = Arc::new(key);
+
+    Arc::new(move |event, env| {
+        let key = Arc::clone(&key);
+        let limiter = limiter.clone();
+
+        Box::pin(async move {
+            let allowed = match inject(&*key, &event, &env) {
+                Some(key) => Some(limiter.check(key().await)),
+                None => None,
+            };
+            (event, allowed)
+        })
+    })
+}
+
+fn verdict(allowed: Option<bool>) -> Verdict {
+    if allowed == Some(true) {
+        Verdict::Pass
+    } else {
+        Verdict::Reject
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::ControlFlow;
+
+    use super::*;
+    use crate::{deps, di::DependencyMap, endpoint, entry};
+
+    #[tokio::test]
+    async fn rate_limit() {
+        let clock = ManualClock::new();
+        let limiter = RateLimiter::with_clock(Quota::per_second(2), clock.clone());
+        let handler: Handler<DependencyMap, &str> = entry()
+            .rate_limit_or(
+                |user: u32| user,
+                limiter.clone(),
+                endpoint(|| async { "Too many requests" }),
+            )
+            .endpoint(|| async { "OK" });
+
+        assert_eq!(handler.dispatch(deps![1u32]).await, ControlFlow::Break("OK"));
+        assert_eq!(handler.dispatch(deps![1u32]).await, ControlFlow::Break("OK"));
+        assert_eq!(handler.dispatch(deps![1u32]).await, ControlFlow::Break("Too many requests"));
+        assert_eq!(handler.dispatch(deps![2u32]).await, ControlFlow::Break("OK"));
+
+        // A cell is released every 500ms.
+        clock.advance(Duration::from_millis(499));
+        assert_eq!(handler.dispatch(deps![1u32]).await, ControlFlow::Break("Too many requests"));
+        clock.advance(Duration::from_millis(1));
+        assert_eq!(handler.dispatch(deps![1u32]).await, ControlFlow::Break("OK"));
+        assert_eq!(handler.dispatch(deps![1u32]).await, ControlFlow::Break("Too many requests"));
+
+        // The limiter is shared, and the events over the limit are filtered out.
+        let filtered: Handler<DependencyMap, &str> =
+            entry().rate_limit(|user: u32| user, limiter.clone()).endpoint(|| async { "OK" });
+        assert_eq!(filtered.dispatch(deps![1u32]).await, ControlFlow::Continue(deps![1u32]));
+
+        // Idle keys are forgotten.
+        for user in 3..=MIN_CLEANUP_AT as u32 {
+            assert!(limiter.check(user));
+        }
+        clock.advance(Duration::from_secs(1));
+        assert!(limiter.check(0));
+        assert_eq!(limiter.state.lock().unwrap().arrivals.len(), 1);
+
+        // The next event of a quota that overflows the time never comes, unless
+        // the burst is unlimited as well.
+        let limiter = RateLimiter::with_clock(Quota::new(1, Duration::MAX), clock.clone());
+        assert!(limiter.check(0));
+        clock.advance(Duration::from_secs(1));
+        assert!(!limiter.check(0));
+        let limiter =
+            RateLimiter::with_clock(Quota::new(2, Duration::MAX).burst(u32::MAX), clock.clone());
+        assert!((0..3).all(|_| limiter.check(0)));
+        assert!(std::panic::catch_unwind(|| Quota::new(2, Duration::from_nanos(1))).is_err());
+    }
+}
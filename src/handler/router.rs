@@ -0,0 +1,386 @@
+//! Routing events by paths, such as the paths of HTTP requests or the
+//! commands of CLI tools.
+//!
+//! See [`Router`].
+
+use std::{
+    collections::HashMap,
+    fmt::{Display, Formatter},
+    ops::ControlFlow,
+    str::FromStr,
+    sync::Arc,
+};
+
+use crate::{
+    description::{self, NodeKind},
+    di::{Asyncify, DependencySupplier, Insert},
+    handler::{
+        core::from_fn_with_context,
+        error::inject,
+        trace::{self, NodeInfo, Verdict},
+    },
+    Handler, HandlerDescription,
+};
+
+/// A set of routes, each of which is a path pattern with a handler.
+///
+/// A pattern consists of segments separated by `/`. A segment can be:
+///
+///  - a literal, e.g. `users`, which matches itself;
+///  - `:name`, which matches any segment, capturing it as the parameter
+///    `name`;
+///  - `*`, which matches any segment;
+///  - `*name`, the last segment only, which matches the rest of the path
+///    (possibly empty), capturing it as the parameter `name`.
+///
+/// Empty segments are ignored, i.e., `/users/` is the same as `users`, and
+/// so is the path to be matched. Also, the query of the path (starting with
+/// `?`) is ignored.
+///
+/// The patterns are compiled into a trie, so the time to find a route depends
+/// on the length of the path, not on the number of routes. If several
+/// patterns match a path, the literals take precedence over the parameters,
+/// which take precedence over `*`, which take precedence over `*name`, from
+/// left to right.
+///
+/// # Examples
+///
+/// ```
+/// use dptree::{prelude::*, router::{Params, Router}};
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let handler: Handler<_, _> = Router::new()
+///     .route("/users/me", dptree::endpoint(|| async { "me".to_owned() }))
+///     .route(
+///         "/users/:id/posts/:post",
+///         dptree::endpoint(|params: Params| async move {
+///             format!("post {} of {}", params.get("post").unwrap(), params.get("id").unwrap())
+///         }),
+///     )
+///     .route(
+///         "/files/*path",
+///         dptree::endpoint(|params: Params| async move {
+///             format!("file {}", params.get("path").unwrap())
+///         }),
+///     )
+///     .build::<&str>()
+///     .branch(dptree::endpoint(|| async { "404 Not Found".to_owned() }));
+///
+/// assert_eq!(handler.dispatch(dptree::deps!["/users/me"]).await, ControlFlow::Break("me".to_owned()));
+/// assert_eq!(
+///     handler.dispatch(dptree::deps!["/users/42/posts/7"]).await,
+///     ControlFlow::Break("post 7 of 42".to_owned())
+/// );
+/// assert_eq!(
+///     handler.dispatch(dptree::deps!["/files/a/b.txt?raw"]).await,
+///     ControlFlow::Break("file a/b.txt".to_owned())
+/// );
+/// assert_eq!(
+///     handler.dispatch(dptree::deps!["/users"]).await,
+///     ControlFlow::Break("404 Not Found".to_owned())
+/// );
+/// # }
+/// ```
+#[must_use]
+pub struct Router<'a, Input, Output, Descr = description::Unspecified> {
+    root: Node,
+    routes: Vec<Handler<'a, Input, Output, Descr>>,
+}
+
+impl<'a, Input, Output, Descr> Default for Router<'a, Input, Output, Descr> {
+    fn default() -> Self {
+        Self { root: Node::default(), routes: Vec::new() }
+    }
+}
+
+impl<'a, Input, Output, Descr> Router<'a, Input, Output, Descr>
+where
+    Input: Send + 'a,
+    Output: 'a,
+    Descr: HandlerDescription,
+{
+    /// Constructs a router without routes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Executes `handler` for the paths that match `pattern`, with the
+    /// captured parameters as [`Params`].
+    ///
+    /// # Panics
+    ///
+    /// If `pattern` is malformed (e.g., `*name` is not the last segment, or
+    /// a parameter has no name), conflicts with another pattern (e.g.,
+    /// `/users/:id` and `/users/:name/posts`), or has been added already.
+    pub fn route(mut self, pattern: &str, handler: Handler<'a, Input, Output, Descr>) -> Self {
+        if let Err(error) = self.root.insert(pattern, self.routes.len()) {
+            panic!("Invalid route `{}`: {}", pattern, error);
+        }
+        self.routes.push(handler);
+        self
+    }
+
+    /// Constructs a handler that matches the path of the type `P` against the
+    /// routes.
+    ///
+    /// If some route matches, its handler is executed with [`Params`] added
+    /// to the container. If there is no such route, or its handler continues
+    /// the execution, the handler continues it with the original input.
+    ///
+    /// The path is described as a dependency of [`crate::filter_map`] that
+    /// provides [`Params`], and the handlers of the routes are branched from
+    /// it.
+    #[must_use]
+    #[track_caller]
+    pub fn build<P>(self) -> Handler<'a, Input, Output, Descr>
+    where
+        P: AsRef<str> + Clone + Send + Sync + 'static,
+        Input: DependencySupplier<P> + Insert<Params> + Clone + Sync,
+    {
+        let Self { root, routes } = self;
+        let root = Arc::new(root);
+        let find = Arc::new(Asyncify(move |path: P| root.find(path.as_ref())));
+
+        let description = routes.iter().fold(
+            Descr::filter_map().with_dependencies(
+                <Input as DependencySupplier<P>>::supplied_type().into_iter().collect(),
+                <Input as Insert<Params>>::inserted_type().into_iter().collect(),
+            ),
+            |description, route| description.merge_branch(route.description()),
+        );
+        let node = NodeInfo::new(NodeKind::FilterMap);
+        let routes: Arc<[_]> = routes.into();
+
+        from_fn_with_context(description, Some(node), move |event: Input, cont, ctx| {
+            let find = Arc::clone(&find);
+            let routes = Arc::clone(&routes);
+
+            async move {
+                let Some(find) = inject(&*find, &event, &ctx.env) else {
+                    return ControlFlow::Continue(event);
+                };
+                let found = trace::evaluate(&ctx.env, node, find(), |found| match found {
+                    Some(_) => Verdict::Pass,
+                    None => Verdict::Reject,
+                })
+                .await;
+                std::mem::drop(find);
+
+                if let Some((route, params)) = found {
+                    let mut inner = event.clone();
+                    inner.insert(params);
+
+                    let result = routes[route].dispatch_in(inner, ctx).await;
+                    if let ControlFlow::Break(output) = result {
+                        return ControlFlow::Break(output);
+                    }
+                }
+
+                cont(event).await
+            }
+        })
+    }
+}
+
+/// The parameters captured from a path by a [`Router`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Params(Vec<(Arc<str>, String)>);
+
+impl Params {
+    /// Returns the value of the parameter `name`, if it is captured.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0.iter().find(|(param, _)| &**param == name).map(|(_, value)| value.as_str())
+    }
+
+    /// Parses the value of the parameter `name`.
+    ///
+    /// Returns `None` if the parameter is not captured or cannot be parsed.
+    /// To reject the paths with malformed parameters, use it in
+    /// [`crate::filter_map`], e.g., `dptree::filter_map(|params: Params|
+    /// params.parse::<u64>("id"))`.
+    pub fn parse<T: FromStr>(&self, name: &str) -> Option<T> {
+        self.get(name)?.parse().ok()
+    }
+
+    /// Iterates over the names and the values of the parameters, in the order
+    /// of the segments of the pattern.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(name, value)| (&**name, value.as_str()))
+    }
+}
+
+/// A node of the trie of patterns, corresponding to a sequence of segments.
+#[derive(Default)]
+struct Node {
+    literals: HashMap<String, Node>,
+    param: Option<(Arc<str>, Box<Node>)>,
+    wildcard: Option<Box<Node>>,
+    /// The name of the parameter and the route of `*name`.
+    rest: Option<(Arc<str>, usize)>,
+    /// The route of the pattern ending here.
+    route: Option<usize>,
+}
+
+#[derive(Debug)]
+enum PatternError {
+    UnnamedParam,
+    RestNotLast,
+    Conflict(Arc<str>),
+    Duplicate,
+}
+
+impl Display for PatternError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnnamedParam => f.write_str("a parameter has no name"),
+            Self::RestNotLast => f.write_str("`*name` must be the last segment"),
+            Self::Conflict(name) => {
+                write!(f, "the parameter conflicts with `{}` of another route", name)
+            }
+            Self::Duplicate => f.write_str("the route has been added already"),
+        }
+    }
+}
+
+impl Node {
+    fn insert(&mut self, pattern: &str, route: usize) -> Result<(), PatternError> {
+        let mut node = self;
+        let mut segments = segments(pattern).peekable();
+
+        while let Some(segment) = segments.next() {
+            node = if let Some(name) = segment.strip_prefix(':') {
+                if name.is_empty() {
+                    return Err(PatternError::UnnamedParam);
+                }
+
+                let (param, next) = node.param.get_or_insert_with(|| (name.into(), Box::default()));
+                if &**param != name {
+                    return Err(PatternError::Conflict(Arc::clone(param)));
+                }
+                next
+            } else if segment == "*" {
+                node.wildcard.get_or_insert_with(Box::default)
+            } else if let Some(name) = segment.strip_prefix('*') {
+                if segments.peek().is_some() {
+                    return Err(PatternError::RestNotLast);
+                }
+                if node.rest.is_some() {
+                    return Err(PatternError::Duplicate);
+                }
+
+                node.rest = Some((name.into(), route));
+                return Ok(());
+            } else {
+                node.literals.entry(segment.to_owned()).or_default()
+            };
+        }
+
+        match node.route {
+            Some(_) => Err(PatternError::Duplicate),
+            None => {
+                node.route = Some(route);
+                Ok(())
+            }
+        }
+    }
+
+    /// Returns the route matching `path` and the captured parameters.
+    fn find(&self, path: &str) -> Option<(usize, Params)> {
+        let segments: Vec<_> = segments(path.split('?').next().unwrap_or_default()).collect();
+        let mut params = Vec::new();
+
+        self.find_from(&segments, &mut params).map(|route| (route, Params(params)))
+    }
+
+    fn find_from(&self, segments: &[&str], params: &mut Vec<(Arc<str>, String)>) -> Option<usize> {
+        match segments.split_first() {
+            None => {
+                if let Some(route) = self.route {
+                    return Some(route);
+                }
+            }
+            Some((segment, rest)) => {
+                if let Some(route) =
+                    self.literals.get(*segment).and_then(|next| next.find_from(rest, params))
+                {
+                    return Some(route);
+                }
+                if let Some((name, next)) = &self.param {
+                    params.push((Arc::clone(name), (*segment).to_owned()));
+                    if let Some(route) = next.find_from(rest, params) {
+                        return Some(route);
+                    }
+                    params.pop();
+                }
+                if let Some(route) =
+                    self.wildcard.as_ref().and_then(|next| next.find_from(rest, params))
+                {
+                    return Some(route);
+                }
+            }
+        }
+
+        let (name, route) = self.rest.as_ref()?;
+        params.push((Arc::clone(name), segments.join("/")));
+        Some(*route)
+    }
+}
+
+fn segments(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|segment| !segment.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{deps, di::DependencyMap, endpoint, filter_map};
+
+    #[tokio::test]
+    async fn router() {
+        let show = |route: &'static str| {
+            endpoint(move |params: Params| async move {
+                let params: Vec<_> =
+                    params.iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+                format!("{} {}", route, params.join(","))
+            })
+        };
+        let handler: Handler<DependencyMap, String> = Router::new()
+            .route("/", show("root"))
+            .route("/users/me", show("me"))
+            .route("/users/:id", show("user"))
+            .route(
+                "/users/:id/posts/:post",
+                filter_map(|params: Params| params.parse::<u32>("post"))
+                    .endpoint(|post: u32| async move { format!("post {}", post) }),
+            )
+            .route("/users/:id/*", show("any"))
+            .route("/users/*rest", show("rest"))
+            .build::<String>();
+
+        let dispatch = |path: &str| handler.dispatch(deps![path.to_owned()]);
+        assert_eq!(dispatch("").await, ControlFlow::Break("root ".to_owned()));
+        assert_eq!(dispatch("/users/me/").await, ControlFlow::Break("me ".to_owned()));
+        assert_eq!(dispatch("/users/1?x=2").await, ControlFlow::Break("user id=1".to_owned()));
+        assert_eq!(dispatch("/users/1/posts/2").await, ControlFlow::Break("post 2".to_owned()));
+        // The route is matched, but its handler continues the execution.
+        assert_eq!(
+            dispatch("/users/1/posts/x").await,
+            ControlFlow::Continue(deps!["/users/1/posts/x".to_owned()])
+        );
+        assert_eq!(dispatch("/users/1/comments").await, ControlFlow::Break("any id=1".to_owned()));
+        assert_eq!(
+            dispatch("/users/1/a/b").await,
+            ControlFlow::Break("rest rest=1/a/b".to_owned())
+        );
+        assert_eq!(dispatch("/users").await, ControlFlow::Break("rest rest=".to_owned()));
+        assert_eq!(dispatch("/posts").await, ControlFlow::Continue(deps!["/posts".to_owned()]));
+
+        let mut root = Node::default();
+        assert!(root.insert("/users/:id", 0).is_ok());
+        assert!(matches!(root.insert("/users/:name/posts", 1), Err(PatternError::Conflict(_))));
+        assert!(matches!(root.insert("/users/:id", 1), Err(PatternError::Duplicate)));
+        assert!(matches!(root.insert("/*rest/x", 1), Err(PatternError::RestNotLast)));
+        assert!(matches!(root.insert("/:", 1), Err(PatternError::UnnamedParam)));
+    }
+}
@@ -0,0 +1,290 @@
+//! Integration with [`tracing`](https://docs.rs/tracing), enabled by the
+//! `tracing` feature.
+//!
+//! Without the feature, the functions of this module are no-ops.
+
+use std::{future::Future, ops::ControlFlow};
+
+use crate::handler::{compile::Step, trace::NodeInfo};
+
+#[cfg(feature = "tracing")]
+use tracing::{field, trace_span, Instrument};
+
+/// Executes a handler described by `node` inside of a `handler` span.
+///
+/// `execute` is called in the span as well, since user-defined handlers may do
+/// some work before returning a future. Once the handler has finished, its
+/// outcome (`continue` or `break`) is recorded into the span.
+#[cfg(feature = "tracing")]
+pub(crate) async fn handler<Fut, Input, Output>(
+    node: NodeInfo,
+    execute: impl FnOnce() -> Fut,
+) -> ControlFlow<Output, Input>
+where
+    Fut: Future<Output = ControlFlow<Output, Input>>,
+{
+    let span = trace_span!(
+        "handler",
+        kind = %node.kind,
+        location = %node.location,
+        outcome = field::Empty,
+    );
+
+    let result = span.in_scope(execute).instrument(span.clone()).await;
+    span.record("outcome", outcome(&result));
+    result
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) async fn handler<Fut, Input, Output>(
+    _node: NodeInfo,
+    execute: impl FnOnce() -> Fut,
+) -> ControlFlow<Output, Input>
+where
+    Fut: Future<Output = ControlFlow<Output, Input>>,
+{
+    execute().await
+}
+
+/// The `handler` spans of the built-in handlers whose continuations are being
+/// executed by a [compiled](crate::Handler::compile) handler.
+///
+/// Since the interpreter does not nest the handlers, it keeps their spans open
+/// by itself, so that they cover the continuations the same way as
+/// [`handler`] does.
+#[cfg(feature = "tracing")]
+pub(crate) struct Spans(Vec<tracing::Span>);
+
+#[cfg(feature = "tracing")]
+impl Spans {
+    /// Whether the spans are recorded at all.
+    pub(crate) const ENABLED: bool = true;
+
+    pub(crate) fn new() -> Self {
+        Spans(Vec::new())
+    }
+
+    /// Executes a built-in handler described by `node` inside of a `handler`
+    /// span.
+    ///
+    /// If the handler passes the input further, its span is kept open until
+    /// [`Spans::close`] or [`Spans::break_all`].
+    pub(crate) async fn step<Fut, Input, Output>(
+        &mut self,
+        node: NodeInfo,
+        fut: Fut,
+    ) -> Step<Output, Input>
+    where
+        Fut: Future<Output = Step<Output, Input>>,
+    {
+        let span = self.innermost().in_scope(|| {
+            trace_span!(
+                "handler",
+                kind = %node.kind,
+                location = %node.location,
+                outcome = field::Empty,
+            )
+        });
+
+        let step = fut.instrument(span.clone()).await;
+        match step {
+            Step::Pass(_) | Step::Scoped { .. } => self.0.push(span),
+            Step::Reject(_) => {
+                span.record("outcome", "continue");
+            }
+            Step::Break(_) => {
+                span.record("outcome", "break");
+            }
+            Step::Fail { .. } => {
+                span.record("outcome", "fail");
+            }
+            Step::Abort(_) => {
+                span.record("outcome", "abort");
+            }
+        }
+        step
+    }
+
+    /// Closes the innermost span, since the execution has been continued past
+    /// its handler.
+    pub(crate) fn close(&mut self) {
+        if let Some(span) = self.0.pop() {
+            span.record("outcome", "continue");
+        }
+    }
+
+    /// Closes all the spans, since the execution has been broken.
+    pub(crate) fn break_all(&mut self) {
+        for span in self.0.drain(..).rev() {
+            span.record("outcome", "break");
+        }
+    }
+
+    /// Instruments `fut` with the innermost span.
+    pub(crate) fn instrument<Fut: Future>(&self, fut: Fut) -> impl Future<Output = Fut::Output> {
+        fut.instrument(self.innermost())
+    }
+
+    fn innermost(&self) -> tracing::Span {
+        self.0.last().cloned().unwrap_or_else(tracing::Span::none)
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct Spans;
+
+#[cfg(not(feature = "tracing"))]
+impl Spans {
+    pub(crate) const ENABLED: bool = false;
+
+    pub(crate) fn new() -> Self {
+        Spans
+    }
+
+    pub(crate) async fn step<Fut, Input, Output>(
+        &mut self,
+        _node: NodeInfo,
+        fut: Fut,
+    ) -> Step<Output, Input>
+    where
+        Fut: Future<Output = Step<Output, Input>>,
+    {
+        fut.await
+    }
+
+    pub(crate) fn close(&mut self) {}
+
+    pub(crate) fn break_all(&mut self) {}
+
+    pub(crate) fn instrument<Fut: Future>(&self, fut: Fut) -> impl Future<Output = Fut::Output> {
+        fut
+    }
+}
+
+/// Instruments the future of an endpoint's injected function with an
+/// `endpoint` span.
+#[cfg(feature = "tracing")]
+pub(crate) fn endpoint<Fut: Future>(node: NodeInfo, fut: Fut) -> impl Future<Output = Fut::Output> {
+    fut.instrument(trace_span!("endpoint", location = %node.location))
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn endpoint<Fut: Future>(
+    _node: NodeInfo,
+    fut: Fut,
+) -> impl Future<Output = Fut::Output> {
+    fut
+}
+
+#[cfg(feature = "tracing")]
+fn outcome<Output, Input>(result: &ControlFlow<Output, Input>) -> &'static str {
+    match result {
+        ControlFlow::Continue(_) => "continue",
+        ControlFlow::Break(_) => "break",
+    }
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use std::{
+        fmt::Debug,
+        ops::ControlFlow,
+        sync::{Arc, Mutex},
+    };
+
+    use tracing::{
+        field::{Field, Visit},
+        span, Event, Metadata, Subscriber,
+    };
+
+    use crate::{deps, entry, filter, help_inference};
+
+    /// Records the names and fields of all spans.
+    #[derive(Clone, Default)]
+    struct Recorder {
+        spans: Arc<Mutex<Vec<RecordedSpan>>>,
+    }
+
+    type RecordedSpan = (&'static str, Vec<(String, String)>);
+
+    struct Fields<'a>(&'a mut Vec<(String, String)>);
+
+    impl Visit for Fields<'_> {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0.push((field.name().to_owned(), value.to_owned()));
+        }
+
+        fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+            self.0.push((field.name().to_owned(), format!("{:?}", value)));
+        }
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &span::Attributes<'_>) -> span::Id {
+            let mut fields = Vec::new();
+            span.record(&mut Fields(&mut fields));
+            let mut spans = self.spans.lock().unwrap();
+            spans.push((span.metadata().name(), fields));
+
+            // Identifiers must be non-zero.
+            span::Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, span: &span::Id, values: &span::Record<'_>) {
+            let mut spans = self.spans.lock().unwrap();
+            values.record(&mut Fields(&mut spans[span.into_u64() as usize - 1].1));
+        }
+
+        fn record_follows_from(&self, _span: &span::Id, _follows: &span::Id) {}
+
+        fn event(&self, _event: &Event<'_>) {}
+
+        fn enter(&self, _span: &span::Id) {}
+
+        fn exit(&self, _span: &span::Id) {}
+    }
+
+    #[tokio::test]
+    async fn spans() {
+        let handler = help_inference(entry())
+            .branch(filter(|x: i32| x > 0).endpoint(|| async { "positive" }))
+            .branch(entry().endpoint(|| async { "other" }));
+
+        let recorder = Recorder::default();
+        let result = {
+            let _guard = tracing::subscriber::set_default(recorder.clone());
+            handler.dispatch(deps![-1]).await
+        };
+        assert_eq!(result, ControlFlow::Break("other"));
+
+        let spans = recorder.spans.lock().unwrap();
+        let summary: Vec<_> = spans
+            .iter()
+            .map(|(name, fields)| {
+                let field = |name: &str| {
+                    fields.iter().find(|(field, _)| field == name).map(|(_, value)| value.as_str())
+                };
+                (*name, field("kind"), field("outcome"))
+            })
+            .collect();
+
+        assert_eq!(
+            summary,
+            [
+                ("handler", Some("entry"), Some("break")),
+                ("handler", Some("filter"), Some("continue")),
+                ("handler", Some("entry"), Some("break")),
+                ("handler", Some("endpoint"), Some("break")),
+                ("endpoint", None, None),
+            ]
+        );
+        assert!(spans[0]
+            .1
+            .iter()
+            .any(|(field, value)| field == "location" && value.contains(file!())));
+    }
+}
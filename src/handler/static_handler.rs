@@ -0,0 +1,492 @@
+//! Statically dispatched handlers.
+//!
+//! A [`Handler`] stores its function as `Arc<dyn Fn>` and returns a boxed
+//! future; so do the functions injected into it. This is flexible, but for
+//! trivial handlers executed very often, the dynamic dispatch and the
+//! allocations may dominate. The handlers of this module are plain generic
+//! types instead: a tree of them is a single concrete type, which is executed
+//! without boxing. Once the hot part of a tree is built, it can be turned into
+//! a [`Handler`] with [`StaticHandler::boxed`], and combined with the rest as
+//! usual.
+//!
+//! The functions are injected with [`StaticInjectable`], which takes the
+//! dependencies from a container the same way as [`Injectable`] does. If a
+//! dependency is missing, the container panics (e.g., [`DependencyMap`]
+//! does), since [`Handler::try_dispatch`] only supports [`Injectable`].
+//!
+//! # Examples
+//!
+//! ```
+//! use dptree::{prelude::*, static_handler::{self, StaticHandler}};
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let hot = static_handler::entry()
+//!     .branch(static_handler::filter(|x: i32| x > 0).endpoint(|| async { "positive" }))
+//!     .branch(static_handler::filter(|x: i32| x < 0).endpoint(|| async { "negative" }));
+//! assert_eq!(hot.dispatch(dptree::deps![1]).await, ControlFlow::Break("positive"));
+//!
+//! let handler: Handler<_, _> =
+//!     hot.boxed().branch(dptree::endpoint(|| async { "zero" }));
+//! assert_eq!(handler.dispatch(dptree::deps![0]).await, ControlFlow::Break("zero"));
+//! # }
+//! ```
+//!
+//! [`Injectable`]: crate::di::Injectable
+//! [`DependencyMap`]: crate::di::DependencyMap
+
+use std::{future::Future, marker::PhantomData, ops::ControlFlow, sync::Arc};
+
+use futures::future::{ready, Ready};
+
+use crate::{
+    di::{Asyncify, DependencySupplier, Insert},
+    from_fn_with_description, Handler, HandlerDescription,
+};
+
+/// A handler whose type describes its whole structure.
+///
+/// See [the module-level documentation](self).
+pub trait StaticHandler<Input, Output>: Send + Sync {
+    /// Executes this handler with a continuation.
+    ///
+    /// See [`Handler::execute`].
+    fn execute<C, Fut>(
+        &self,
+        input: Input,
+        cont: C,
+    ) -> impl Future<Output = ControlFlow<Output, Input>> + Send
+    where
+        C: FnOnce(Input) -> Fut + Send,
+        Fut: Future<Output = ControlFlow<Output, Input>> + Send;
+
+    /// Executes this handler.
+    ///
+    /// See [`Handler::dispatch`].
+    fn dispatch(&self, input: Input) -> impl Future<Output = ControlFlow<Output, Input>> + Send
+    where
+        Input: Send,
+        Output: Send,
+    {
+        self.execute(input, |input| ready(ControlFlow::Continue(input)))
+    }
+
+    /// Turns this handler into [`Handler`].
+    ///
+    /// The resulting handler is described as a user-defined one.
+    #[track_caller]
+    fn boxed<'a, Descr>(self) -> Handler<'a, Input, Output, Descr>
+    where
+        Self: Sized + 'a,
+        Input: Send + 'a,
+        Output: Send + 'a,
+        Descr: HandlerDescription,
+    {
+        let this = Arc::new(self);
+
+        from_fn_with_description(Descr::user_defined(), move |input, cont| {
+            let this = Arc::clone(&this);
+            async move { this.execute(input, cont).await }
+        })
+    }
+}
+
+/// A function that can be called with the dependencies from a container of
+/// type `Input`, without boxing.
+///
+/// This is implemented for asynchronous functions, and for synchronous
+/// functions wrapped into [`Asyncify`], with up to 12 parameters. See also
+/// [`Injectable`](crate::di::Injectable).
+pub trait StaticInjectable<Input, Output, FnArgs> {
+    /// The future returned by the function.
+    type Future: Future<Output = Output>;
+
+    /// Calls the function with the dependencies from `container`.
+    fn call(&self, container: &Input) -> Self::Future;
+}
+
+macro_rules! impl_static_injectable {
+    ($($generic:ident),*) => {
+        impl<Func, Input, Output, Fut, $($generic),*> StaticInjectable<Input, Output, ($($generic,)*)> for Func
+        where
+            Input: $(DependencySupplier<$generic> +)*,
+            Func: Fn($($generic),*) -> Fut,
+            Fut: Future<Output = Output>,
+            $($generic: Clone),*
+        {
+            type Future = Fut;
+
+            #[allow(unused_variables)]
+            fn call(&self, container: &Input) -> Fut {
+                self($(std::borrow::Borrow::<$generic>::borrow(&DependencySupplier::<$generic>::get(container)).clone()),*)
+            }
+        }
+
+        impl<Func, Input, Output, $($generic),*> StaticInjectable<Input, Output, ($($generic,)*)> for Asyncify<Func>
+        where
+            Input: $(DependencySupplier<$generic> +)*,
+            Func: Fn($($generic),*) -> Output,
+            $($generic: Clone),*
+        {
+            type Future = Ready<Output>;
+
+            #[allow(unused_variables)]
+            fn call(&self, container: &Input) -> Ready<Output> {
+                let Asyncify(this) = self;
+                ready(this($(std::borrow::Borrow::<$generic>::borrow(&DependencySupplier::<$generic>::get(container)).clone()),*))
+            }
+        }
+    };
+}
+
+impl_static_injectable!();
+impl_static_injectable!(T1);
+impl_static_injectable!(T1, T2);
+impl_static_injectable!(T1, T2, T3);
+impl_static_injectable!(T1, T2, T3, T4);
+impl_static_injectable!(T1, T2, T3, T4, T5);
+impl_static_injectable!(T1, T2, T3, T4, T5, T6);
+impl_static_injectable!(T1, T2, T3, T4, T5, T6, T7);
+impl_static_injectable!(T1, T2, T3, T4, T5, T6, T7, T8);
+impl_static_injectable!(T1, T2, T3, T4, T5, T6, T7, T8, T9);
+impl_static_injectable!(T1, T2, T3, T4, T5, T6, T7, T8, T9, T10);
+impl_static_injectable!(T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11);
+impl_static_injectable!(T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12);
+
+/// The handler returned by [`entry`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Entry;
+
+/// The handler returned by [`filter`] and [`filter_async`].
+pub struct Filter<F, Args> {
+    pred: F,
+    args: PhantomData<fn() -> Args>,
+}
+
+/// The handler returned by [`filter_map`] and [`filter_map_async`].
+pub struct FilterMap<F, Args, NewType> {
+    proj: F,
+    args: PhantomData<fn() -> (Args, NewType)>,
+}
+
+/// The handler returned by [`map`] and [`map_async`].
+pub struct Map<F, Args, NewType> {
+    proj: F,
+    args: PhantomData<fn() -> (Args, NewType)>,
+}
+
+/// The handler returned by [`endpoint`].
+pub struct Endpoint<F, Args> {
+    f: F,
+    args: PhantomData<fn() -> Args>,
+}
+
+/// The handler returned by the `chain` methods of the static handlers.
+#[derive(Debug, Clone)]
+pub struct Chain<First, Second> {
+    first: First,
+    second: Second,
+}
+
+/// The handler returned by the `branch` methods of the static handlers.
+#[derive(Debug, Clone)]
+pub struct Branch<First, Second> {
+    first: First,
+    second: Second,
+}
+
+macro_rules! impl_combinators {
+    ($($ty:ident $(<$($param:ident),*>)?),*) => {
+        $(
+            impl$(<$($param),*>)? $ty$(<$($param),*>)? {
+                /// Chains `next` to this handler.
+                ///
+                /// See [`Handler::chain`].
+                #[must_use]
+                pub fn chain<Next>(self, next: Next) -> Chain<Self, Next> {
+                    Chain { first: self, second: next }
+                }
+
+                /// Branches `next` from this handler.
+                ///
+                /// See [`Handler::branch`].
+                #[must_use]
+                pub fn branch<Next>(self, next: Next) -> Branch<Self, Next> {
+                    Branch { first: self, second: next }
+                }
+
+                /// Chains an [`endpoint`] to this handler.
+                #[must_use]
+                pub fn endpoint<Func, FnArgs>(self, f: Func) -> Chain<Self, Endpoint<Func, FnArgs>> {
+                    self.chain(endpoint(f))
+                }
+            }
+        )*
+    };
+}
+
+// The combinators are inherent methods, since the input and output types of
+// a handler are only known once it is executed.
+impl_combinators!(
+    Entry,
+    Filter<F, Args>,
+    FilterMap<F, Args, NewType>,
+    Map<F, Args, NewType>,
+    Endpoint<F, Args>,
+    Chain<First, Second>,
+    Branch<First, Second>
+);
+
+/// The static version of [`crate::entry`].
+#[must_use]
+pub fn entry() -> Entry {
+    Entry
+}
+
+/// The static version of [`crate::filter`].
+#[must_use]
+pub fn filter<Pred, FnArgs>(pred: Pred) -> Filter<Asyncify<Pred>, FnArgs> {
+    Filter { pred: Asyncify(pred), args: PhantomData }
+}
+
+/// The static version of [`crate::filter_async`].
+#[must_use]
+pub fn filter_async<Pred, FnArgs>(pred: Pred) -> Filter<Pred, FnArgs> {
+    Filter { pred, args: PhantomData }
+}
+
+/// The static version of [`crate::filter_map`].
+#[must_use]
+pub fn filter_map<Projection, Args, NewType>(
+    proj: Projection,
+) -> FilterMap<Asyncify<Projection>, Args, NewType> {
+    FilterMap { proj: Asyncify(proj), args: PhantomData }
+}
+
+/// The static version of [`crate::filter_map_async`].
+#[must_use]
+pub fn filter_map_async<Projection, Args, NewType>(
+    proj: Projection,
+) -> FilterMap<Projection, Args, NewType> {
+    FilterMap { proj, args: PhantomData }
+}
+
+/// The static version of [`crate::map`].
+#[must_use]
+pub fn map<Projection, Args, NewType>(
+    proj: Projection,
+) -> Map<Asyncify<Projection>, Args, NewType> {
+    Map { proj: Asyncify(proj), args: PhantomData }
+}
+
+/// The static version of [`crate::map_async`].
+#[must_use]
+pub fn map_async<Projection, Args, NewType>(proj: Projection) -> Map<Projection, Args, NewType> {
+    Map { proj, args: PhantomData }
+}
+
+/// The static version of [`crate::endpoint`].
+#[must_use]
+pub fn endpoint<F, FnArgs>(f: F) -> Endpoint<F, FnArgs> {
+    Endpoint { f, args: PhantomData }
+}
+
+impl<Input, Output> StaticHandler<Input, Output> for Entry {
+    fn execute<C, Fut>(
+        &self,
+        input: Input,
+        cont: C,
+    ) -> impl Future<Output = ControlFlow<Output, Input>> + Send
+    where
+        C: FnOnce(Input) -> Fut + Send,
+        Fut: Future<Output = ControlFlow<Output, Input>> + Send,
+    {
+        cont(input)
+    }
+}
+
+impl<F, Args, Input, Output> StaticHandler<Input, Output> for Filter<F, Args>
+where
+    F: StaticInjectable<Input, bool, Args> + Send + Sync,
+    F::Future: Send,
+    Input: Send,
+{
+    fn execute<C, Fut>(
+        &self,
+        input: Input,
+        cont: C,
+    ) -> impl Future<Output = ControlFlow<Output, Input>> + Send
+    where
+        C: FnOnce(Input) -> Fut + Send,
+        Fut: Future<Output = ControlFlow<Output, Input>> + Send,
+    {
+        let cond = self.pred.call(&input);
+
+        async move {
+            if cond.await {
+                cont(input).await
+            } else {
+                ControlFlow::Continue(input)
+            }
+        }
+    }
+}
+
+impl<F, Args, NewType, Input, Output> StaticHandler<Input, Output> for FilterMap<F, Args, NewType>
+where
+    F: StaticInjectable<Input, Option<NewType>, Args> + Send + Sync,
+    F::Future: Send,
+    Input: Insert<NewType> + Clone + Send,
+{
+    fn execute<C, Fut>(
+        &self,
+        input: Input,
+        cont: C,
+    ) -> impl Future<Output = ControlFlow<Output, Input>> + Send
+    where
+        C: FnOnce(Input) -> Fut + Send,
+        Fut: Future<Output = ControlFlow<Output, Input>> + Send,
+    {
+        let res = self.proj.call(&input);
+
+        async move {
+            let value = match res.await {
+                Some(value) => value,
+                None => return ControlFlow::Continue(input),
+            };
+            let mut next = input.clone();
+            next.insert(value);
+
+            match cont(next).await {
+                ControlFlow::Continue(_) => ControlFlow::Continue(input),
+                done => done,
+            }
+        }
+    }
+}
+
+impl<F, Args, NewType, Input, Output> StaticHandler<Input, Output> for Map<F, Args, NewType>
+where
+    F: StaticInjectable<Input, NewType, Args> + Send + Sync,
+    F::Future: Send,
+    Input: Insert<NewType> + Clone + Send,
+{
+    fn execute<C, Fut>(
+        &self,
+        input: Input,
+        cont: C,
+    ) -> impl Future<Output = ControlFlow<Output, Input>> + Send
+    where
+        C: FnOnce(Input) -> Fut + Send,
+        Fut: Future<Output = ControlFlow<Output, Input>> + Send,
+    {
+        let res = self.proj.call(&input);
+
+        async move {
+            let mut next = input.clone();
+            next.insert(res.await);
+
+            match cont(next).await {
+                ControlFlow::Continue(_) => ControlFlow::Continue(input),
+                done => done,
+            }
+        }
+    }
+}
+
+impl<F, Args, Input, Output> StaticHandler<Input, Output> for Endpoint<F, Args>
+where
+    F: StaticInjectable<Input, Output, Args> + Send + Sync,
+    F::Future: Send,
+{
+    fn execute<C, Fut>(
+        &self,
+        input: Input,
+        _cont: C,
+    ) -> impl Future<Output = ControlFlow<Output, Input>> + Send
+    where
+        C: FnOnce(Input) -> Fut + Send,
+        Fut: Future<Output = ControlFlow<Output, Input>> + Send,
+    {
+        let output = self.f.call(&input);
+
+        async move { ControlFlow::Break(output.await) }
+    }
+}
+
+impl<First, Second, Input, Output> StaticHandler<Input, Output> for Chain<First, Second>
+where
+    First: StaticHandler<Input, Output>,
+    Second: StaticHandler<Input, Output>,
+{
+    fn execute<C, Fut>(
+        &self,
+        input: Input,
+        cont: C,
+    ) -> impl Future<Output = ControlFlow<Output, Input>> + Send
+    where
+        C: FnOnce(Input) -> Fut + Send,
+        Fut: Future<Output = ControlFlow<Output, Input>> + Send,
+    {
+        self.first.execute(input, move |input| self.second.execute(input, cont))
+    }
+}
+
+impl<First, Second, Input, Output> StaticHandler<Input, Output> for Branch<First, Second>
+where
+    First: StaticHandler<Input, Output>,
+    Second: StaticHandler<Input, Output>,
+    Input: Send,
+    Output: Send,
+{
+    fn execute<C, Fut>(
+        &self,
+        input: Input,
+        cont: C,
+    ) -> impl Future<Output = ControlFlow<Output, Input>> + Send
+    where
+        C: FnOnce(Input) -> Fut + Send,
+        Fut: Future<Output = ControlFlow<Output, Input>> + Send,
+    {
+        self.first.execute(input, move |input| async move {
+            match self.second.dispatch(input).await {
+                ControlFlow::Continue(input) => cont(input).await,
+                done => done,
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{deps, di::DependencyMap};
+
+    #[tokio::test]
+    async fn static_handler() {
+        let handler = entry()
+            .branch(filter(|x: i32| x == 0).endpoint(|| async { "zero".to_owned() }))
+            .branch(
+                filter_map(|x: i32| x.checked_mul(10))
+                    .chain(filter(|y: i32| y > 100))
+                    .endpoint(|y: i32| async move { format!("big {}", y) }),
+            )
+            .branch(map(|x: i32| x.to_string()).endpoint(|s: String| async move { s }))
+            .chain(filter(|| false));
+
+        let check = |result: ControlFlow<String, DependencyMap>, expected: &str| {
+            assert_eq!(result, ControlFlow::Break(expected.to_owned()))
+        };
+        check(handler.dispatch(deps![0]).await, "zero");
+        check(handler.dispatch(deps![11]).await, "big 110");
+        check(handler.dispatch(deps![5]).await, "5");
+
+        let boxed: Handler<DependencyMap, String> =
+            filter(|x: i32| x > 0).endpoint(|| async { "positive".to_owned() }).boxed();
+        let boxed = crate::entry()
+            .branch(boxed)
+            .branch(crate::endpoint(|x: i32| async move { x.to_string() }));
+        check(boxed.dispatch(deps![1]).await, "positive");
+        check(boxed.dispatch(deps![-1]).await, "-1");
+    }
+}
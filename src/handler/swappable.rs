@@ -0,0 +1,137 @@
+use std::{ops::ControlFlow, sync::Arc};
+
+use arc_swap::ArcSwap;
+
+use crate::{
+    description::{self, NodeKind},
+    handler::{core::from_fn_with_context, trace::NodeInfo},
+    Handler, HandlerDescription,
+};
+
+/// A handler that can be replaced at run time.
+///
+/// Every dispatch executes the handler that is current at its beginning, so
+/// replacing it with [`SwappableHandler::swap`] does not affect the dispatches
+/// in progress: they finish with the old handler, which is dropped afterwards.
+/// Clones of a swappable handler share the current handler.
+///
+/// To embed a swappable handler into another handler (e.g., to reload a part
+/// of a tree only), use [`SwappableHandler::handler`].
+///
+/// # Examples
+///
+/// ```
+/// use dptree::{prelude::*, SwappableHandler};
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let routes: SwappableHandler<_, _> =
+///     SwappableHandler::new(dptree::endpoint(|| async { "old" }));
+/// let handler: Handler<_, _> = dptree::filter(|x: i32| x > 0).chain(routes.handler());
+///
+/// assert_eq!(handler.dispatch(dptree::deps![1]).await, ControlFlow::Break("old"));
+///
+/// routes.swap(dptree::endpoint(|| async { "new" }));
+/// assert_eq!(handler.dispatch(dptree::deps![1]).await, ControlFlow::Break("new"));
+/// # }
+/// ```
+pub struct SwappableHandler<'a, Input, Output, Descr = description::Unspecified> {
+    current: Arc<ArcSwap<Handler<'a, Input, Output, Descr>>>,
+}
+
+impl<'a, Input, Output, Descr> Clone for SwappableHandler<'a, Input, Output, Descr> {
+    fn clone(&self) -> Self {
+        Self { current: Arc::clone(&self.current) }
+    }
+}
+
+impl<'a, Input, Output, Descr> SwappableHandler<'a, Input, Output, Descr>
+where
+    Input: Send + 'a,
+    Output: 'a,
+    Descr: HandlerDescription,
+{
+    /// Constructs a swappable handler with `handler` being current.
+    pub fn new(handler: Handler<'a, Input, Output, Descr>) -> Self {
+        Self { current: Arc::new(ArcSwap::from_pointee(handler)) }
+    }
+
+    /// Returns the current handler.
+    ///
+    /// This can be used to query its description.
+    #[must_use]
+    pub fn load(&self) -> Handler<'a, Input, Output, Descr> {
+        Handler::clone(&self.current.load_full())
+    }
+
+    /// Makes `handler` current, returning the previous handler.
+    pub fn swap(
+        &self,
+        handler: Handler<'a, Input, Output, Descr>,
+    ) -> Handler<'a, Input, Output, Descr> {
+        let previous = self.current.swap(Arc::new(handler));
+        Arc::try_unwrap(previous).unwrap_or_else(|previous| Handler::clone(&previous))
+    }
+
+    /// Executes the current handler.
+    ///
+    /// See [`Handler::dispatch`].
+    pub async fn dispatch(&self, container: Input) -> ControlFlow<Output, Input> {
+        self.load().dispatch(container).await
+    }
+
+    /// Constructs a handler that executes the handler current at the moment.
+    ///
+    /// Since its description is fixed at this point, it is described as a
+    /// user-defined handler, whatever the current handler is.
+    #[must_use]
+    #[track_caller]
+    pub fn handler(&self) -> Handler<'a, Input, Output, Descr> {
+        let this = self.clone();
+
+        from_fn_with_context(
+            Descr::user_defined(),
+            Some(NodeInfo::new(NodeKind::UserDefined)),
+            move |event, cont, ctx| this.load().execute_in(event, cont, ctx),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::oneshot;
+
+    use super::*;
+    use crate::{deps, di::DependencyMap, endpoint};
+
+    #[tokio::test]
+    async fn swappable_handler() {
+        let (release, released) = oneshot::channel();
+        let released = Arc::new(tokio::sync::Mutex::new(Some(released)));
+
+        let swappable: SwappableHandler<DependencyMap, &str> =
+            SwappableHandler::new(endpoint(move || {
+                let released = Arc::clone(&released);
+                async move {
+                    if let Some(released) = released.lock().await.take() {
+                        released.await.unwrap();
+                    }
+                    "old"
+                }
+            }));
+
+        // The dispatch in progress finishes with the old handler.
+        let in_progress = tokio::spawn({
+            let swappable = swappable.clone();
+            async move { swappable.dispatch(deps![]).await }
+        });
+        tokio::task::yield_now().await;
+        let old = swappable.swap(endpoint(|| async { "new" }));
+
+        assert_eq!(swappable.dispatch(deps![]).await, ControlFlow::Break("new"));
+        assert_eq!(swappable.handler().dispatch(deps![]).await, ControlFlow::Break("new"));
+        release.send(()).unwrap();
+        assert_eq!(in_progress.await.unwrap(), ControlFlow::Break("old"));
+        assert_eq!(old.dispatch(deps![]).await, ControlFlow::Break("old"));
+    }
+}
@@ -0,0 +1,236 @@
+use std::{collections::HashMap, hash::Hash, ops::ControlFlow, sync::Arc};
+
+use futures::future::BoxFuture;
+
+use crate::{
+    description::{self, NodeKind},
+    di::{Asyncify, Injectable},
+    handler::{
+        context::Env,
+        core::from_fn_with_context,
+        error::inject,
+        trace::{self, NodeInfo, Verdict},
+    },
+    Handler, HandlerDescription,
+};
+
+/// Constructs a handler that computes a key with `key` and executes the
+/// handler added for this key with [`Switch::case`].
+///
+/// Unlike `dptree::entry().branch(a).branch(b)...`, where the filters of the
+/// branches are evaluated one by one, the key is computed once, and the
+/// matching case is looked up in a [`HashMap`]. If there is no such case, or
+/// it continues the execution, the [`Switch::default`] handler (if any) is
+/// executed; if it continues the execution as well, the handler continues it
+/// with the original input.
+///
+/// The key function is described as [`crate::filter_map`], and the cases and
+/// the default handler are branched from it.
+///
+/// # Examples
+///
+/// ```
+/// use dptree::prelude::*;
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let handler: Handler<_, _> = dptree::switch(|cmd: &'static str| cmd.split(' ').next().unwrap())
+///     .case("/start", dptree::endpoint(|| async { "Hello!".to_owned() }))
+///     .case(
+///         "/echo",
+///         dptree::endpoint(|cmd: &'static str| async move { cmd["/echo ".len()..].to_owned() }),
+///     )
+///     .default(dptree::endpoint(|| async { "Unknown command".to_owned() }))
+///     .build();
+///
+/// assert_eq!(handler.dispatch(dptree::deps!["/start"]).await, ControlFlow::Break("Hello!".to_owned()));
+/// assert_eq!(handler.dispatch(dptree::deps!["/echo hi"]).await, ControlFlow::Break("hi".to_owned()));
+/// assert_eq!(
+///     handler.dispatch(dptree::deps!["/help"]).await,
+///     ControlFlow::Break("Unknown command".to_owned())
+/// );
+/// # }
+/// ```
+#[track_caller]
+pub fn switch<'a, F, Input, Output, Key, Args, Descr>(
+    key: F,
+) -> Switch<'a, Input, Output, Key, Descr>
+where
+    Asyncify<F>: Injectable<Input, Key, Args> + Send + Sync + 'a,
+    Input: Send + 'a,
+    Output: 'a,
+    Key: Hash + Eq + Send + Sync + 'a,
+    Descr: HandlerDescription,
+{
+    switch_with_description(Descr::filter_map(), Asyncify(key))
+}
+
+/// The asynchronous version of [`switch`].
+#[track_caller]
+pub fn switch_async<'a, F, Input, Output, Key, Args, Descr>(
+    key: F,
+) -> Switch<'a, Input, Output, Key, Descr>
+where
+    F: Injectable<Input, Key, Args> + Send + Sync + 'a,
+    Input: Send + 'a,
+    Output: 'a,
+    Key: Hash + Eq + Send + Sync + 'a,
+    Descr: HandlerDescription,
+{
+    switch_with_description(Descr::filter_map_async(), key)
+}
+
+#[track_caller]
+fn switch_with_description<'a, F, Input, Output, Key, Args, Descr>(
+    description: Descr,
+    key: F,
+) -> Switch<'a, Input, Output, Key, Descr>
+where
+    F: Injectable<Input, Key, Args> + Send + Sync + 'a,
+    Input: Send + 'a,
+    Output: 'a,
+    Key: Hash + Eq + Send + Sync + 'a,
+    Descr: HandlerDescription,
+{
+    let description = description
+        .with_dependencies(<F as Injectable<Input, Key, Args>>::input_types(), Vec::new());
+    let node = NodeInfo::new(NodeKind::FilterMap);
+    let key = Arc::new(key);
+
+    Switch {
+        key: Arc::new(move |event, env| {
+            let key = Arc::clone(&key);
+
+            Box::pin(async move {
+                let key = match inject(&*key, &event, &env) {
+                    Some(key) => Some(key().await),
+                    None => None,
+                };
+                (event, key)
+            })
+        }),
+        node,
+        cases: HashMap::new(),
+        default: None,
+        description,
+    }
+}
+
+/// A handler under construction, created by [`switch`].
+#[must_use]
+pub struct Switch<'a, Input, Output, Key, Descr = description::Unspecified> {
+    key: Arc<KeyFn<'a, Input, Key>>,
+    node: NodeInfo,
+    cases: HashMap<Key, Handler<'a, Input, Output, Descr>>,
+    default: Option<Handler<'a, Input, Output, Descr>>,
+    description: Descr,
+}
+
+/// Returns `None` if the dispatch has failed.
+type KeyFn<'a, Input, Key> =
+    dyn Fn(Input, Env) -> BoxFuture<'a, (Input, Option<Key>)> + Send + Sync + 'a;
+
+impl<'a, Input, Output, Key, Descr> Switch<'a, Input, Output, Key, Descr>
+where
+    Input: Send + 'a,
+    Output: 'a,
+    Key: Hash + Eq + Send + Sync + 'a,
+    Descr: HandlerDescription,
+{
+    /// Executes `handler` when the key is equal to `key`.
+    ///
+    /// # Panics
+    ///
+    /// If a case for `key` has already been added.
+    pub fn case(mut self, key: Key, handler: Handler<'a, Input, Output, Descr>) -> Self {
+        self.description = self.description.merge_branch(handler.description());
+        assert!(self.cases.insert(key, handler).is_none(), "A case for this key is already added");
+        self
+    }
+
+    /// Executes `handler` when there is no case for the key, or the case
+    /// continues the execution.
+    ///
+    /// # Panics
+    ///
+    /// If a default handler has already been set.
+    pub fn default(mut self, handler: Handler<'a, Input, Output, Descr>) -> Self {
+        assert!(self.default.is_none(), "A default handler is already set");
+        self.description = self.description.merge_branch(handler.description());
+        self.default = Some(handler);
+        self
+    }
+
+    /// Constructs the handler.
+    pub fn build(self) -> Handler<'a, Input, Output, Descr> {
+        let Self { key, node, cases, default, description } = self;
+        let cases = Arc::new(cases);
+
+        from_fn_with_context(description, Some(node), move |event, cont, ctx| {
+            let key = Arc::clone(&key);
+            let cases = Arc::clone(&cases);
+            let default = default.clone();
+
+            async move {
+                let (mut event, key) =
+                    trace::evaluate(&ctx.env, node, key(event, ctx.env.clone()), |(_, key)| {
+                        match key {
+                            Some(key) if cases.contains_key(key) || default.is_some() => {
+                                Verdict::Pass
+                            }
+                            _ => Verdict::Reject,
+                        }
+                    })
+                    .await;
+                let key = match key {
+                    Some(key) => key,
+                    None => return ControlFlow::Continue(event),
+                };
+
+                for handler in cases.get(&key).into_iter().chain(&default) {
+                    match handler.dispatch_in(event, ctx.clone()).await {
+                        ControlFlow::Continue(next) => event = next,
+                        done => return done,
+                    }
+                }
+
+                cont(event).await
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{deps, di::DependencyMap, endpoint, filter};
+
+    #[tokio::test]
+    async fn switch() {
+        #[derive(Clone, Copy, Hash, PartialEq, Eq)]
+        enum Command {
+            Add,
+            Sub,
+            Help,
+        }
+
+        let handler: Handler<DependencyMap, i32> = super::switch(|cmd: Command| cmd)
+            .case(Command::Add, endpoint(|x: i32| async move { x + 1 }))
+            .case(Command::Sub, filter(|x: i32| x > 0).endpoint(|x: i32| async move { x - 1 }))
+            .default(filter(|x: i32| x != 0).endpoint(|| async { 0 }))
+            .build()
+            .branch(endpoint(|| async { -1 }));
+
+        assert_eq!(handler.dispatch(deps![Command::Add, 5]).await, ControlFlow::Break(6));
+        assert_eq!(handler.dispatch(deps![Command::Sub, 5]).await, ControlFlow::Break(4));
+        // The case continues the execution, so the default handler is executed.
+        assert_eq!(handler.dispatch(deps![Command::Sub, -5]).await, ControlFlow::Break(0));
+        assert_eq!(handler.dispatch(deps![Command::Help, 5]).await, ControlFlow::Break(0));
+        // The default handler continues the execution as well.
+        assert_eq!(handler.dispatch(deps![Command::Help, 0]).await, ControlFlow::Break(-1));
+
+        let no_default: Handler<DependencyMap, i32> =
+            super::switch(|x: i32| x % 2).case(0, endpoint(|| async { 0 })).build();
+        assert_eq!(no_default.dispatch(deps![1]).await, ControlFlow::Continue(deps![1]));
+    }
+}
@@ -0,0 +1,154 @@
+//! Interoperability with [`tower`](https://docs.rs/tower), enabled by the
+//! `tower` feature.
+
+use std::{
+    convert::Infallible,
+    ops::ControlFlow,
+    task::{Context, Poll},
+};
+
+use futures::future::{poll_fn, BoxFuture};
+use tower_service::Service;
+
+use crate::{
+    di::{DependencySupplier, Insert},
+    endpoint_result, Endpoint, Handler, HandlerDescription,
+};
+
+/// A handler is a [`Service`] that dispatches its requests (see
+/// [`Handler::dispatch`]).
+///
+/// The handler is always ready, and it never fails: the result of the dispatch
+/// is the response. This lets tower middleware, such as timeouts, concurrency
+/// limits, and load shedding, wrap handler trees.
+impl<'a, Input, Output, Descr> Service<Input> for Handler<'a, Input, Output, Descr>
+where
+    Input: Send + 'a,
+    Output: 'a,
+    Descr: HandlerDescription,
+{
+    type Response = ControlFlow<Output, Input>;
+    type Error = Infallible;
+    type Future = BoxFuture<'a, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, container: Input) -> Self::Future {
+        let this = self.clone();
+        Box::pin(async move { Ok(this.dispatch(container).await) })
+    }
+}
+
+/// Constructs an endpoint that calls `service` with the `Request` dependency.
+///
+/// `service` is cloned for each call, and the clone is driven to readiness
+/// before being called. If the call succeeds, the execution is broken with the
+/// response; otherwise, the error is routed as in [`crate::endpoint_result`]
+/// (see [`Handler::on_error`]).
+///
+/// # Examples
+///
+/// ```
+/// use std::{
+///     convert::Infallible,
+///     future::{ready, Ready},
+///     task::{Context, Poll},
+/// };
+///
+/// use dptree::prelude::*;
+/// use tower_service::Service;
+///
+/// #[derive(Clone)]
+/// struct Greeter;
+///
+/// impl Service<&'static str> for Greeter {
+///     type Response = String;
+///     type Error = Infallible;
+///     type Future = Ready<Result<String, Infallible>>;
+///
+///     fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+///         Poll::Ready(Ok(()))
+///     }
+///
+///     fn call(&mut self, name: &'static str) -> Self::Future {
+///         ready(Ok(format!("Hello, {}!", name)))
+///     }
+/// }
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let handler: Handler<_, _> = dptree::from_service(Greeter);
+///
+/// assert_eq!(
+///     handler.dispatch(dptree::deps!["Alice"]).await,
+///     ControlFlow::Break("Hello, Alice!".to_owned())
+/// );
+/// # }
+/// ```
+#[must_use]
+#[track_caller]
+pub fn from_service<'a, S, Request, Input, Descr>(
+    service: S,
+) -> Endpoint<'a, Input, S::Response, Descr>
+where
+    S: Service<Request> + Clone + Send + Sync + 'static,
+    S::Response: Send + 'a,
+    S::Error: Send,
+    S::Future: Send + 'static,
+    Request: Clone + Send + Sync + 'static,
+    Input: DependencySupplier<Request> + Insert<S::Error> + Clone + Send + Sync + 'a,
+    Descr: HandlerDescription,
+{
+    endpoint_result(move |request: Request| call(service.clone(), request))
+}
+
+/// Calls `service` with `request`, once it is ready.
+async fn call<S, Request>(mut service: S, request: Request) -> Result<S::Response, S::Error>
+where
+    S: Service<Request>,
+{
+    poll_fn(|cx| service.poll_ready(cx)).await?;
+    service.call(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::{ready, Ready};
+
+    use super::*;
+    use crate::{deps, di::DependencyMap, filter};
+
+    #[derive(Clone)]
+    struct Halve;
+
+    impl Service<i32> for Halve {
+        type Response = i32;
+        type Error = &'static str;
+        type Future = Ready<Result<i32, &'static str>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, x: i32) -> Self::Future {
+            ready(if x % 2 == 0 { Ok(x / 2) } else { Err("odd") })
+        }
+    }
+
+    #[tokio::test]
+    async fn tower() {
+        let handler: Handler<DependencyMap, i32> = filter(|x: i32| x > 0)
+            .chain(from_service(Halve))
+            .on_error(crate::endpoint(|e: &'static str| async move { e.len() as i32 }));
+
+        // A handler is a service, ...
+        let mut service = handler.clone();
+        assert_eq!(service.call(deps![4]).await, Ok(ControlFlow::Break(2)));
+        assert_eq!(service.call(deps![-4]).await, Ok(ControlFlow::Continue(deps![-4])));
+
+        // ... and a service is an endpoint, whose errors are routed as usual.
+        assert_eq!(handler.dispatch(deps![3]).await, ControlFlow::Break(3));
+    }
+}
@@ -0,0 +1,210 @@
+//! Recording of the decisions made during a dispatch.
+//!
+//! See [`Handler::dispatch_traced`](crate::Handler::dispatch_traced).
+
+use std::{
+    fmt::{Display, Formatter},
+    future::Future,
+    panic::Location,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    description::NodeKind,
+    handler::{context::Env, observer},
+};
+
+/// Run-time information about a handler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeInfo {
+    /// The kind of the handler.
+    pub kind: NodeKind,
+
+    /// The location where the handler was constructed.
+    pub location: &'static Location<'static>,
+}
+
+impl NodeInfo {
+    #[track_caller]
+    pub(crate) fn new(kind: NodeKind) -> Self {
+        Self { kind, location: Location::caller() }
+    }
+}
+
+impl Display for NodeInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at {}", self.kind, self.location)
+    }
+}
+
+/// A decision made by a handler after its injected function has returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Verdict {
+    /// The handler has passed the event further.
+    Pass,
+
+    /// The handler has rejected the event, i.e., a filter has returned `false`
+    /// or `None`.
+    Reject,
+
+    /// The handler has broken the execution (i.e., it is an endpoint).
+    Break,
+
+    /// The handler has returned an error, which has been routed to an error
+    /// handler (see [`crate::Handler::on_error`]).
+    Fail,
+}
+
+impl Display for Verdict {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Pass => "passed the event",
+            Self::Reject => "rejected the event",
+            Self::Break => "broke the execution",
+            Self::Fail => "returned an error",
+        })
+    }
+}
+
+/// A single record of [`Trace`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TraceEvent {
+    /// A handler has been entered.
+    Entered(NodeInfo),
+
+    /// A subtree named with [`crate::Handler::named`] has been entered.
+    Named(&'static str),
+
+    /// The injected function of a built-in handler has returned.
+    Evaluated {
+        /// The handler.
+        node: NodeInfo,
+
+        /// The time spent in the injected function (including its future).
+        elapsed: Duration,
+
+        /// The decision of the handler.
+        verdict: Verdict,
+    },
+}
+
+impl Display for TraceEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Entered(node) => write!(f, "entered {}", node),
+            Self::Named(name) => write!(f, "entered `{}`", name),
+            Self::Evaluated { node, elapsed, verdict } => {
+                write!(f, "{} {} in {:?}", node, verdict, elapsed)
+            }
+        }
+    }
+}
+
+/// A chronological record of what happened during a dispatch.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Trace {
+    /// The recorded events, in the order in which they have happened.
+    pub events: Vec<TraceEvent>,
+}
+
+impl Display for Trace {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for event in &self.events {
+            writeln!(f, "{}", event)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Records `event` into the trace of `env`, if any.
+fn record(env: &Env, event: TraceEvent) {
+    if let Some(trace) = &env.trace {
+        trace.lock().unwrap_or_else(|error| error.into_inner()).events.push(event);
+    }
+}
+
+/// Records that `node` has been entered.
+pub(crate) fn entered(env: &Env, node: NodeInfo) {
+    record(env, TraceEvent::Entered(node));
+    observer::entered(env, node);
+}
+
+/// Records that a subtree named `name` has been entered.
+pub(crate) fn named(env: &Env, name: &'static str) {
+    record(env, TraceEvent::Named(name));
+}
+
+/// Awaits the future of an injected function of `node`, recording the time
+/// spent and the resulting verdict (both into the trace and to the observers
+/// of `env`).
+pub(crate) async fn evaluate<Fut>(
+    env: &Env,
+    node: NodeInfo,
+    fut: Fut,
+    verdict: impl FnOnce(&Fut::Output) -> Verdict,
+) -> Fut::Output
+where
+    Fut: Future,
+{
+    if env.trace.is_none() && env.observers.is_none() {
+        return fut.await;
+    }
+
+    let start = Instant::now();
+    let output = fut.await;
+    let (elapsed, verdict) = (start.elapsed(), verdict(&output));
+
+    record(env, TraceEvent::Evaluated { node, elapsed, verdict });
+    observer::evaluated(env, node, verdict, elapsed);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::ControlFlow;
+
+    use super::*;
+    use crate::{deps, endpoint, entry, filter, filter_map, help_inference};
+
+    #[tokio::test]
+    async fn dispatch_traced() {
+        let handler = help_inference(entry())
+            .branch(filter(|x: i32| x > 0).endpoint(|| async { "positive" }))
+            .branch(filter_map(|x: i32| x.checked_neg()).endpoint(|| async { "negated" }));
+
+        let (result, trace) = handler.dispatch_traced(deps![-1]).await;
+        assert_eq!(result, ControlFlow::Break("negated"));
+
+        let events: Vec<_> = trace
+            .events
+            .iter()
+            .map(|event| match event {
+                TraceEvent::Entered(node) => (node.kind, None),
+                TraceEvent::Evaluated { node, verdict, .. } => (node.kind, Some(*verdict)),
+                TraceEvent::Named(name) => panic!("Unexpected name: {}", name),
+            })
+            .collect();
+        assert_eq!(
+            events,
+            [
+                (NodeKind::Entry, None),
+                (NodeKind::Filter, None),
+                (NodeKind::Filter, Some(Verdict::Reject)),
+                (NodeKind::FilterMap, None),
+                (NodeKind::FilterMap, Some(Verdict::Pass)),
+                (NodeKind::Endpoint, None),
+                (NodeKind::Endpoint, Some(Verdict::Break)),
+            ]
+        );
+
+        // The dispatches started by the handlers are not recorded.
+        let nested = help_inference(endpoint(move |x: i32| {
+            let handler = handler.clone();
+            async move { handler.dispatch(deps![x]).await.is_break() }
+        }));
+        let (result, trace) = nested.dispatch_traced(deps![-1]).await;
+        assert_eq!(result, ControlFlow::Break(true));
+        assert_eq!(trace.events.len(), 2);
+    }
+}
@@ -54,11 +54,14 @@
 //!
 //! [chain (tree) of responsibility]: https://en.wikipedia.org/wiki/Chain-of-responsibility_pattern
 
+mod dispatcher;
 mod handler;
+mod names;
 
 pub mod di;
 pub mod prelude;
 
+pub use dispatcher::{Dispatcher, Spawner};
 pub use handler::*;
 
 /// Filters an enumeration, passing its payload forwards.
@@ -0,0 +1,38 @@
+//! Names of the handler subtrees that are being executed.
+//!
+//! See [`crate::Handler::named`].
+
+use std::fmt::{Display, Formatter};
+
+/// Names of the nested named subtrees, from the outermost to the innermost.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Path(Vec<&'static str>);
+
+impl Path {
+    pub(crate) fn join(&self, name: &'static str) -> Self {
+        let mut names = self.0.clone();
+        names.push(name);
+        Self(names)
+    }
+}
+
+impl Display for Path {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (i, name) in self.0.iter().enumerate() {
+            if i != 0 {
+                f.write_str(" > ")?;
+            }
+            f.write_str(name)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Formats `error`, mentioning the handler at `path`, if it is named.
+pub(crate) fn describe(path: Option<&Path>, error: &dyn Display) -> String {
+    match path {
+        Some(path) => format!("In `{}`: {}", path, error),
+        None => error.to_string(),
+    }
+}